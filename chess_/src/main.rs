@@ -1,32 +1,53 @@
 mod wrap;
-use std::{fs::File, sync::Arc};
+use std::{
+  fs::File,
+  sync::Arc,
+  thread,
+  time::{Duration, Instant},
+};
 
 use lib::FullyObservableDeterministicMctsProblem;
 use mcts::{
   bandits::{Puct, Uct},
+  concurrent::ConcurrentSearch,
   forest::render::save,
   rollout::RandomRollout,
-  search::Search as Searchv2,
-  SearchLimit,
+  search::{Search as Searchv2, VirtualLoss},
+  SearchBackend, SearchLimit,
 };
 use wrap::Game;
 
 fn bench3() {
   let args: Vec<String> = std::env::args().collect();
   let count: u32 = args.get(1).map(|arg| arg.parse().unwrap()).unwrap();
+  // optional wall clock budget in seconds, the search is stopped externally
+  let seconds: Option<u64> = args.get(2).map(|arg| arg.parse().unwrap());
   let g = Arc::new(Game {});
   let state = Arc::new(g.start_state());
   let limit = SearchLimit::new(count);
-  let search = Arc::new(Searchv2::new(
-    g,
-    state,
-    1,
-    limit,
-    Uct(2.5),
-    RandomRollout(120),
-  ));
+  let search = Arc::new(
+    Searchv2::new(g, state, 1, limit, Uct(2.5), RandomRollout(120))
+      .with_virtual_loss(VirtualLoss::Loss(1))
+      .with_progress(Duration::from_secs(1), |info| {
+        println!(
+          "select_count: {}, nps: {:.0}, best: {:?}, pv: {:?}",
+          info.select_count,
+          info.nodes_per_sec,
+          info.best_action.map(|m| m.to_string()),
+          info.pv.iter().map(|m| m.to_string()).collect::<Vec<_>>()
+        );
+      }),
+  );
   let wc = 12;
 
+  if let Some(seconds) = seconds {
+    let stop = search.stop_handle();
+    thread::spawn(move || {
+      thread::sleep(Duration::from_secs(seconds));
+      stop.stop();
+    });
+  }
+
   crossbeam::scope(|s| {
     for _ in 0..wc {
       s.spawn(|_| {
@@ -42,6 +63,55 @@ fn bench3() {
   //save(&forest, File::create("chess.dot").unwrap(), 5000, 10);
 }
 
+// compares the global lock and the per node lock backends for increasing
+// thread counts, usage: chess_ scaling <count>
+fn scaling() {
+  let args: Vec<String> = std::env::args().collect();
+  let count: u32 = args.get(2).map(|arg| arg.parse().unwrap()).unwrap();
+  let g = Arc::new(Game {});
+  let state = Arc::new(g.start_state());
+  println!("threads,global_lock_nps,per_node_lock_nps");
+  for wc in [1, 2, 4, 8, 12] {
+    let limit = SearchLimit::new(count);
+    let global = Searchv2::new(
+      g.clone(),
+      state.clone(),
+      1,
+      limit.clone(),
+      Uct(2.5),
+      RandomRollout(120),
+    );
+    let per_node = ConcurrentSearch::new(
+      g.clone(),
+      state.clone(),
+      1,
+      limit,
+      Uct(2.5),
+      RandomRollout(120),
+    );
+    let global_nps = count as f32 / run(&global, wc).as_secs_f32();
+    let per_node_nps = count as f32 / run(&per_node, wc).as_secs_f32();
+    println!("{wc},{global_nps:.0},{per_node_nps:.0}");
+  }
+}
+
+fn run<S: SearchBackend<Game> + Sync>(search: &S, wc: usize) -> Duration {
+  let start = Instant::now();
+  crossbeam::scope(|s| {
+    for _ in 0..wc {
+      s.spawn(|_| {
+        let mut worker = search.create_workers(1);
+        search.start(&mut worker[0]);
+      });
+    }
+  })
+  .unwrap();
+  start.elapsed()
+}
+
 fn main() {
-  bench3();
+  match std::env::args().nth(1).as_deref() {
+    Some("scaling") => scaling(),
+    _ => bench3(),
+  }
 }
@@ -1,5 +1,5 @@
 use chess::{Board, BoardStatus, ChessMove, Color, MoveGen};
-use lib::FullyObservableDeterministicMctsProblem;
+use lib::{utils::Bounds, FullyObservableDeterministicMctsProblem};
 
 pub struct Game;
 
@@ -42,6 +42,14 @@ impl FullyObservableDeterministicMctsProblem for Game {
   fn agents(&self) -> Vec<Self::Agent> {
     vec![Player(Color::Black), Player(Color::White)]
   }
+
+  fn state_hash(&self, state: &Self::State) -> Option<u64> {
+    Some(state.get_hash())
+  }
+
+  fn value_bounds(&self) -> Option<Vec<Bounds>> {
+    Some(vec![Bounds::new_known(0.0, 1.0); 2])
+  }
 }
 
 impl TryFrom<u8> for Player {
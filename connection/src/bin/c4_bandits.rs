@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use connection::connect4::{Move, State, C4};
+use lib::FullyObservableDeterministicMctsProblem;
+use mcts::{
+  bandits::{Bandit, Divergence, KlUcb, Posterior, Thompson, Ucb1Tuned, UcbV, Uct},
+  rollout::RandomRollout,
+  search::Search,
+  FinalSelection, SearchLimit,
+};
+
+type Board = State<6, 7>;
+
+// rewards of the given side, 0 for red and 1 for blue, and of its opponent in
+// one game, each side searching from scratch every move. a win is worth 1, so
+// both are 0 after a draw
+fn play<R, B>(red: R, blue: B, iterations: u32, side: usize) -> (f32, f32)
+where
+  R: Bandit<Board, Move, Move>,
+  B: Bandit<Board, Move, Move>,
+{
+  let game = Arc::new(C4);
+  let mut state = game.start_state();
+  let mut red_to_move = true;
+  let mut rewards = (0.0, 0.0);
+  while !game.check_terminal(&state) {
+    let limit = SearchLimit::new(iterations);
+    let state_arc = Arc::new(state.clone());
+    let policy = if red_to_move {
+      let search = Search::new(game.clone(), state_arc, 1, limit, red, RandomRollout(50));
+      search.start(&mut search.create_workers(1)[0]);
+      search.get_policy()
+    } else {
+      let search = Search::new(game.clone(), state_arc, 1, limit, blue, RandomRollout(50));
+      search.start(&mut search.create_workers(1)[0]);
+      search.get_policy()
+    };
+    let action = FinalSelection::RobustChild.select(&policy, 0);
+    let reward = game.apply_action(&mut state, &action);
+    rewards.0 += reward[side];
+    rewards.1 += reward[1 - side];
+    red_to_move = !red_to_move;
+  }
+  rewards
+}
+
+// wins, draws and losses of the bandit against uct, half of the games with
+// each color
+fn compare<B>(name: &str, bandit: B, games: u32, iterations: u32)
+where
+  B: Bandit<Board, Move, Move>,
+{
+  let uct = Uct(2.4);
+  let (mut wins, mut draws) = (0, 0);
+  for game in 0..games {
+    let (own, opponent) = if game % 2 == 0 {
+      play(bandit, uct, iterations, 0)
+    } else {
+      play(uct, bandit, iterations, 1)
+    };
+    if own > 0.0 {
+      wins += 1;
+    } else if opponent == 0.0 {
+      draws += 1;
+    }
+  }
+  let percent = |n: u32| 100.0 * n as f32 / games as f32;
+  println!(
+    "{name} against uct: {:.1}% wins, {:.1}% draws, {:.1}% losses",
+    percent(wins),
+    percent(draws),
+    percent(games - wins - draws)
+  );
+}
+
+fn main() {
+  let mut args = std::env::args().skip(1);
+  let games: u32 = args.next().map_or(20, |a| a.parse().unwrap());
+  let iterations: u32 = args.next().map_or(2000, |a| a.parse().unwrap());
+  compare("ucb1-tuned", Ucb1Tuned, games, iterations);
+  compare("ucb-v", UcbV(1.0, 1.2), games, iterations);
+  compare(
+    "kl-ucb bernoulli",
+    KlUcb(Divergence::Bernoulli, 0.0),
+    games,
+    iterations,
+  );
+  compare(
+    "kl-ucb gaussian",
+    KlUcb(Divergence::Gaussian(0.25), 0.0),
+    games,
+    iterations,
+  );
+  compare(
+    "thompson beta",
+    Thompson(Posterior::Beta),
+    games,
+    iterations,
+  );
+  compare(
+    "thompson gaussian",
+    Thompson(Posterior::Gaussian),
+    games,
+    iterations,
+  );
+}
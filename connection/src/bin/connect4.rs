@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use text_io::read;
 
 use connection::connect4::{C4, Move};
@@ -12,6 +12,8 @@ fn main() {
   let prompt = ">";
   let game: Arc<C4<6, 7> >= Arc::new(C4);
   let mut current_state = game.start_state();
+  // reused across drops, until the board is cleared
+  let mut search: Option<Search<C4<6, 7>, Uct, RandomRollout>> = None;
   loop {
     print!("{}", prompt);
     let command: String = read!();
@@ -21,29 +23,64 @@ fn main() {
       }
       "clear" => {
         current_state = game.start_state();
+        search = None;
       }
       "drop" => {
         let col: u8 = read!();
         game.apply_action(&mut current_state, &Move(col));
+        if let Some(search) = search.as_mut() {
+          search.advance(
+            Arc::new(current_state.clone()),
+            &Move(col),
+            &[Move(col), Move(col)],
+          );
+        }
       }
       "analyse" => {
         //let lim = read!();
         let lim = 100000;
-        let limit = SearchLimit::new(lim);
-        let search = Search::new(
-          game.clone(),
-          Arc::new(current_state.clone()),
-          1,
-          limit,
-          Uct(2.4),
-          RandomRollout(50),
-        );
+        let limit = SearchLimit::timed(lim, Duration::from_secs(2));
+        let search = search.get_or_insert_with(|| {
+          Search::new(
+            game.clone(),
+            Arc::new(current_state.clone()),
+            1,
+            limit,
+            Uct(2.4),
+            RandomRollout(50),
+          )
+          .with_transpositions()
+          .with_solver()
+          .with_score_bounds()
+        });
+        // every analysis gets the whole limit, the tree is kept
+        search.restart_budget();
         let mut worker = search.create_workers(1);
         search.start(&mut worker[0]);
         let policy = search.get_policy();
-        for (a, s, v) in policy {
-          println!("{a} -> prob {s:.5}, value: {v:.5}");
+        for entry in policy {
+          print!("{} -> prob {:.5}, value: {:.5}", entry.action, entry.probability, entry.value);
+          match entry.proof {
+            Some(proof) => println!(", {proof}"),
+            None => println!(),
+          }
         }
+        // every tree follows the moves in a fully observable game, the values
+        // along the first one are red's
+        let pv = &search.principal_variations()[0];
+        let line: Vec<_> = pv
+          .iter()
+          .skip(1)
+          .map(|step| {
+            format!(
+              "{} ({}, {:.3})",
+              step.observation.unwrap(),
+              step.select_count,
+              step.value
+            )
+          })
+          .collect();
+        println!("pv: {}", line.join(" "));
       }
       "exit" | "quit" | "bye" => {
         return;
@@ -1,7 +1,11 @@
-use std::fmt::Display;
+use std::{
+  collections::hash_map::DefaultHasher,
+  fmt::Display,
+  hash::{Hash, Hasher},
+};
 
 use fixedbitset::FixedBitSet;
-use lib::FullyObservableDeterministicMctsProblem;
+use lib::{utils::Bounds, FullyObservableDeterministicMctsProblem};
 
 use crate::util::RectBitSet;
 
@@ -89,6 +93,17 @@ impl<const H: usize, const W: usize> FullyObservableDeterministicMctsProblem for
   fn legal_actions(&self, state: &Self::State) -> Vec<Self::Action> {
     (0..W).filter(|col| state.heights[*col] < H as u8).map(|col| Move(col as u8)).collect()
   }
+
+  // the rest of the state follows from the two boards
+  fn state_hash(&self, state: &Self::State) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    state.board.hash(&mut hasher);
+    Some(hasher.finish())
+  }
+
+  fn value_bounds(&self) -> Option<Vec<Bounds>> {
+    Some(vec![Bounds::new_known(0.0, 1.0); 2])
+  }
 }
 
 impl Into<u8> for Color {
@@ -142,8 +157,18 @@ impl<const H: usize, const W: usize> Display for State<H, W> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::*;
-    use mcts::{rollout::RandomRollout, Expansion};
+    use mcts::{
+      bandits::{
+        Bandit, Divergence, KlUcb, Posterior, Rave, RaveSchedule, Thompson, Ucb1Tuned, UcbV, Uct,
+      },
+      rollout::RandomRollout,
+      search::{Gumbel, Search},
+      solver::Outcome,
+      Expansion, SearchLimit,
+    };
 
   #[test]
   fn test1() {
@@ -153,4 +178,270 @@ mod tests {
     let (values, policies) = t.block_expand(&c4, &states);
     println!("{:?}", values);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_state_hash() {
+    let c4: C4<6, 7> = C4{};
+    let mut a = c4.start_state();
+    let mut b = c4.start_state();
+    for col in [0, 1, 2] {
+      c4.apply_action(&mut a, &Move(col));
+    }
+    for col in [2, 1, 0] {
+      c4.apply_action(&mut b, &Move(col));
+    }
+    assert_eq!(c4.state_hash(&a), c4.state_hash(&b));
+    c4.apply_action(&mut b, &Move(3));
+    assert_ne!(c4.state_hash(&a), c4.state_hash(&b));
+  }
+
+  #[test]
+  fn test_transpositions() {
+    let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+    let mut state = c4.start_state();
+    for col in [0, 0, 1, 1, 2, 2] {
+      c4.apply_action(&mut state, &Move(col));
+    }
+    let search = Search::new(
+      c4.clone(),
+      Arc::new(state),
+      1,
+      SearchLimit::new(2000),
+      Uct(2.4),
+      RandomRollout(50),
+    )
+    .with_transpositions();
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let policy = search.get_policy();
+    let best = policy
+      .iter()
+      .max_by(|a, b| a.probability.total_cmp(&b.probability))
+      .unwrap();
+    assert_eq!(best.action, Move(3));
+  }
+
+  #[test]
+  fn test_rave() {
+    let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+    let mut state = c4.start_state();
+    for col in [0, 0, 1, 1, 2] {
+      c4.apply_action(&mut state, &Move(col));
+    }
+    // blue has to block the bottom row
+    let search = Search::new(
+      c4.clone(),
+      Arc::new(state),
+      1,
+      SearchLimit::new(1000),
+      Rave(0.5, RaveSchedule::Equivalence(300.0)),
+      RandomRollout(50),
+    );
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let policy = search.get_policy();
+    let best = policy
+      .iter()
+      .max_by(|a, b| a.probability.total_cmp(&b.probability))
+      .unwrap();
+    assert_eq!(best.action, Move(3));
+  }
+
+  #[test]
+  fn test_variance_bandits() {
+    fn block<B: Bandit<State<6, 7>, Move, Move>>(bandit: B) -> Move {
+      let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+      let mut state = c4.start_state();
+      for col in [0, 0, 1, 1, 2] {
+        c4.apply_action(&mut state, &Move(col));
+      }
+      let search = Search::new(
+        c4.clone(),
+        Arc::new(state),
+        1,
+        SearchLimit::new(2000),
+        bandit,
+        RandomRollout(50),
+      );
+      let mut worker = search.create_workers(1);
+      search.start(&mut worker[0]);
+      let policy = search.get_policy();
+      policy
+        .iter()
+        .max_by(|a, b| a.probability.total_cmp(&b.probability))
+        .unwrap()
+        .action
+    }
+    // blue has to block the bottom row
+    assert_eq!(block(Ucb1Tuned), Move(3));
+    assert_eq!(block(UcbV(1.0, 1.2)), Move(3));
+    assert_eq!(block(KlUcb(Divergence::Bernoulli, 0.0)), Move(3));
+    assert_eq!(block(KlUcb(Divergence::Gaussian(0.25), 0.0)), Move(3));
+    assert_eq!(block(Thompson(Posterior::Beta)), Move(3));
+    assert_eq!(block(Thompson(Posterior::Gaussian)), Move(3));
+  }
+
+  #[test]
+  fn test_gumbel() {
+    let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+    let mut state = c4.start_state();
+    for col in [0, 0, 1, 1, 2] {
+      c4.apply_action(&mut state, &Move(col));
+    }
+    // blue has to block the bottom row, sequential halving finds it with a
+    // small budget
+    let search = Search::new(
+      c4.clone(),
+      Arc::new(state),
+      1,
+      SearchLimit::new(200),
+      Uct(2.4),
+      RandomRollout(50),
+    )
+    .with_gumbel(Gumbel::new(7, 200))
+    .with_seed(0);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    assert_eq!(search.gumbel_action(), Some(Move(3)));
+    let policy = search.get_policy();
+    let total: f32 = policy.iter().map(|e| e.improved).sum();
+    assert!((total - 1.0).abs() < 1e-4);
+    let best = policy
+      .iter()
+      .max_by(|a, b| a.improved.total_cmp(&b.improved))
+      .unwrap();
+    assert_eq!(best.action, Move(3));
+  }
+
+  #[test]
+  fn test_gumbel_advance() {
+    let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+    let mut state = c4.start_state();
+    for col in [6, 0, 6, 1, 5, 2] {
+      c4.apply_action(&mut state, &Move(col));
+    }
+    let mut search = Search::new(
+      c4.clone(),
+      Arc::new(state.clone()),
+      1,
+      SearchLimit::new(200),
+      Uct(2.4),
+      RandomRollout(50),
+    )
+    .with_gumbel(Gumbel::new(7, 200))
+    .with_seed(0);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    c4.apply_action(&mut state, &Move(3));
+    search.advance(Arc::new(state), &Move(3), &[Move(3), Move(3)]);
+
+    // sequential halving starts over at the new root, every action is
+    // searched again before blue settles on blocking the bottom row
+    let counts = |search: &Search<_, _, _>| -> Vec<_> {
+      search.get_policy().iter().map(|e| (e.action, e.select_count)).collect()
+    };
+    let inherited = counts(&search);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let searched = counts(&search);
+    assert_eq!(searched.len(), 7);
+    for (action, count) in searched {
+      let before = inherited.iter().find(|(a, _)| *a == action).map_or(0, |(_, n)| *n);
+      assert!(count > before, "{action:?}");
+    }
+    assert_eq!(search.gumbel_action(), Some(Move(4)));
+  }
+
+  #[test]
+  fn test_solver() {
+    let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+    let search_from = |moves: &[u8]| {
+      let mut state = c4.start_state();
+      for col in moves {
+        c4.apply_action(&mut state, &Move(*col));
+      }
+      let search = Search::new(
+        c4.clone(),
+        Arc::new(state),
+        1,
+        SearchLimit::new(5000),
+        Uct(2.4),
+        RandomRollout(50),
+      )
+      .with_solver();
+      let mut worker = search.create_workers(1);
+      search.start(&mut worker[0]);
+      search
+    };
+
+    // red wins by completing the bottom row, the search stops once it's proven
+    let search = search_from(&[0, 0, 1, 1, 2, 2]);
+    assert!(search.info().select_count < 5000);
+    let policy = search.get_policy();
+    let win = policy.iter().find(|e| e.action == Move(3)).unwrap();
+    let proof = win.proof.as_ref().unwrap();
+    assert_eq!((proof.outcome, proof.depth), (Outcome::Win, 1));
+
+    // exact bounds at the root stop the search as well
+    let mut state = c4.start_state();
+    for col in [0, 0, 1, 1, 2, 2] {
+      c4.apply_action(&mut state, &Move(col));
+    }
+    let search = Search::new(
+      c4.clone(),
+      Arc::new(state),
+      1,
+      SearchLimit::new(5000),
+      Uct(2.4),
+      RandomRollout(50),
+    )
+    .with_score_bounds();
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    assert!(search.info().select_count < 5000);
+
+    // blue has to block, every other move loses in 2
+    let search = search_from(&[0, 0, 1, 1, 2]);
+    for entry in search.get_policy() {
+      if entry.action != Move(3) {
+        let proof = entry.proof.unwrap();
+        assert_eq!((proof.outcome, proof.depth), (Outcome::Loss, 2));
+      }
+    }
+  }
+
+  #[test]
+  fn test_principal_variation() {
+    let c4: Arc<C4<6, 7>> = Arc::new(C4{});
+    let mut state = c4.start_state();
+    for col in [0, 0, 1, 1, 2] {
+      c4.apply_action(&mut state, &Move(col));
+    }
+    let search = Search::new(
+      c4.clone(),
+      Arc::new(state),
+      1,
+      SearchLimit::new(3000),
+      Uct(2.4),
+      RandomRollout(50),
+    );
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    // blue acts at the root of its tree and blocks, then red acts
+    let pv = &search.principal_variations()[1];
+    assert!(pv.len() > 2);
+    assert_eq!(pv[0].action.as_ref().unwrap().action, Move(3));
+    assert_eq!(pv[1].observation, Some(Move(3)));
+    assert!(pv[1].action.is_none());
+    assert!(pv
+      .windows(2)
+      .all(|s| s[0].select_count >= s[1].select_count));
+
+    let forest = search.forest.read().unwrap();
+    let root = forest.cursor(forest.roots()[1]);
+    let block = root.child(&Move(3)).unwrap();
+    assert_eq!(block.select_count(), pv[1].select_count);
+    let most = root.action(&Move(3)).unwrap().select_count;
+    assert!(root.actions().iter().all(|a| a.select_count <= most));
+  }
+}
@@ -4,7 +4,7 @@ use fixedbitset::FixedBitSet;
 
 
 
-#[derive(Clone)]
+#[derive(Clone, Hash)]
 pub struct RectBitSet<const H: usize, const W: usize> {
   internal: FixedBitSet
 }
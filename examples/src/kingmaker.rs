@@ -0,0 +1,142 @@
+use lib::MctsProblem;
+use mcts::Expansion;
+
+// a game of three agents and one move each. the first agent plays safe, losing
+// 0.5, or lets the other two decide. it loses 1 when both of them turn against
+// it, draws when only the third one does, and otherwise wins 1 and rewards
+// them with 1 too, so they make it win unless they play against it
+pub struct Kingmaker;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Agent(u8);
+
+// the moves so far, 0 is the first action of every agent
+pub type State = Vec<usize>;
+
+pub const SAFE: usize = 1;
+// the move of the second and third agents that turns against the first one
+pub const AGAINST: usize = 0;
+
+impl MctsProblem for Kingmaker {
+  type Agent = Agent;
+  type Action = usize;
+  type Observation = usize;
+  type BeliefState = State;
+  type HiddenState = State;
+
+  fn start_state(&self) -> Self::BeliefState {
+    vec![]
+  }
+
+  fn sample_h_state(&self, b_state: &Self::BeliefState) -> Self::HiddenState {
+    b_state.clone()
+  }
+
+  fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
+    b_state.push(*obs);
+  }
+
+  fn agent_to_act(&self, h_state: &Self::HiddenState) -> Self::Agent {
+    Agent(h_state.len() as u8)
+  }
+
+  fn legal_actions(&self, h_state: &Self::HiddenState) -> Vec<Self::Action> {
+    if self.check_terminal(h_state) {
+      vec![]
+    } else {
+      vec![0, 1]
+    }
+  }
+
+  fn apply_action(
+    &self,
+    h_state: &mut Self::HiddenState,
+    action: &Self::Action,
+  ) -> Vec<(f32, Self::Observation)> {
+    h_state.push(*action);
+    let rewards = match h_state.as_slice() {
+      [SAFE, _, _] => [-0.5, 0.0, 0.0],
+      [_, AGAINST, AGAINST] => [-1.0, 0.0, 0.0],
+      [_, _, AGAINST] => [0.0; 3],
+      [_, _, _] => [1.0, 1.0, 1.0],
+      _ => [0.0; 3],
+    };
+    rewards.into_iter().map(|r| (r, *action)).collect()
+  }
+
+  fn check_terminal(&self, h_state: &Self::HiddenState) -> bool {
+    h_state.len() == 3
+  }
+
+  fn agents(&self) -> Vec<Self::Agent> {
+    vec![Agent(0), Agent(1), Agent(2)]
+  }
+}
+
+impl From<Agent> for u8 {
+  fn from(agent: Agent) -> Self {
+    agent.0
+  }
+}
+
+// prefers the moves other than `AGAINST`, so that the agents that don't reply
+// in best reply search don't turn against the first agent
+#[derive(Clone, Copy)]
+pub struct Priors;
+
+impl Expansion<Kingmaker> for Priors {
+  fn expand(&self, p: &Kingmaker, s: &State) -> (Vec<f32>, Vec<(usize, f32)>) {
+    let priors = p
+      .legal_actions(s)
+      .into_iter()
+      .map(|a| (a, if a == AGAINST { 0.25 } else { 0.75 }))
+      .collect();
+    (vec![0.0; 3], priors)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use mcts::{
+    bandits::Uct,
+    search::{Backup, Search},
+    SearchLimit,
+  };
+
+  use super::*;
+
+  fn root_values(backup: Backup) -> (f32, f32) {
+    let search = Search::new(
+      Arc::new(Kingmaker),
+      Arc::new(vec![]),
+      1,
+      SearchLimit::new(20000),
+      Uct(1.0),
+      Priors,
+    )
+    .with_backup(backup);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let policy = search.get_policy();
+    let value = |m| policy.iter().find(|e| e.action == m).unwrap().value;
+    (value(0), value(SAFE))
+  }
+
+  #[test]
+  fn test_backups() {
+    // the other agents make the first one win
+    let (decide, safe) = root_values(Backup::MaxN);
+    assert!((decide - 1.0).abs() < 0.25 && decide > safe, "{decide}");
+
+    // they play together against it
+    let (decide, safe) = root_values(Backup::Paranoid);
+    assert!((decide + 1.0).abs() < 0.25 && decide < safe, "{decide}");
+
+    // only one of them turns against it, the third one as it draws, while
+    // the other one plays its preferred move
+    let (decide, safe) = root_values(Backup::BestReply);
+    assert!(decide.abs() < 0.25 && decide > safe, "{decide}");
+  }
+}
@@ -0,0 +1,298 @@
+use std::fmt::Display;
+
+use lib::MctsProblem;
+use rand::seq::SliceRandom;
+
+// kuhn poker, each of the two players antes 1 and gets one of three cards.
+// the first player checks or bets 1, a bet is called or folded, and a check
+// can be followed by a bet of the second player that the first player calls
+// or folds. the higher card wins the pot at showdown
+pub struct Kuhn;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Card {
+  Jack,
+  Queen,
+  King,
+}
+
+// checks and folds are passes, bets and calls are bets
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Move {
+  Pass,
+  Bet,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Agent(u8);
+
+#[derive(Clone, Debug)]
+pub struct State {
+  cards: [Card; 2],
+  history: Vec<Move>,
+}
+
+// what one of the players knows, its card and the moves so far
+#[derive(Clone, Debug)]
+pub struct Belief {
+  player: usize,
+  card: Card,
+  history: Vec<Move>,
+}
+
+// every player sees the moves, along with its own card
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Observation {
+  pub action: Move,
+  pub card: Card,
+}
+
+const CARDS: [Card; 3] = [Card::Jack, Card::Queen, Card::King];
+
+impl Belief {
+  pub fn new(player: usize, card: Card, history: Vec<Move>) -> Self {
+    Self {
+      player,
+      card,
+      history,
+    }
+  }
+}
+
+impl State {
+  // net winnings of the first player once the hand is over
+  fn payoff(&self) -> Option<f32> {
+    let showdown = if self.cards[0] > self.cards[1] {
+      1.0
+    } else {
+      -1.0
+    };
+    match self.history.as_slice() {
+      [Move::Pass, Move::Pass] => Some(showdown),
+      [Move::Bet, Move::Pass] => Some(1.0),
+      [Move::Pass, Move::Bet, Move::Pass] => Some(-1.0),
+      [Move::Bet, Move::Bet] | [Move::Pass, Move::Bet, Move::Bet] => Some(2.0 * showdown),
+      _ => None,
+    }
+  }
+}
+
+impl MctsProblem for Kuhn {
+  type Agent = Agent;
+  type Action = Move;
+  type Observation = Observation;
+  type BeliefState = Belief;
+  type HiddenState = State;
+
+  fn start_state(&self) -> Self::BeliefState {
+    Belief::new(0, *CARDS.choose(&mut lib::random::rng()).unwrap(), vec![])
+  }
+
+  fn sample_h_state(&self, b_state: &Self::BeliefState) -> Self::HiddenState {
+    let other = *CARDS
+      .iter()
+      .filter(|c| **c != b_state.card)
+      .collect::<Vec<_>>()
+      .choose(&mut lib::random::rng())
+      .unwrap();
+    let mut cards = [*other; 2];
+    cards[b_state.player] = b_state.card;
+    State {
+      cards,
+      history: b_state.history.clone(),
+    }
+  }
+
+  fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
+    b_state.history.push(obs.action);
+  }
+
+  fn agent_to_act(&self, h_state: &Self::HiddenState) -> Self::Agent {
+    Agent(h_state.history.len() as u8 % 2)
+  }
+
+  fn legal_actions(&self, h_state: &Self::HiddenState) -> Vec<Self::Action> {
+    if self.check_terminal(h_state) {
+      vec![]
+    } else {
+      vec![Move::Pass, Move::Bet]
+    }
+  }
+
+  fn apply_action(
+    &self,
+    h_state: &mut Self::HiddenState,
+    action: &Self::Action,
+  ) -> Vec<(f32, Self::Observation)> {
+    h_state.history.push(*action);
+    let payoff = h_state.payoff().unwrap_or(0.0);
+    vec![
+      (
+        payoff,
+        Observation {
+          action: *action,
+          card: h_state.cards[0],
+        },
+      ),
+      (
+        -payoff,
+        Observation {
+          action: *action,
+          card: h_state.cards[1],
+        },
+      ),
+    ]
+  }
+
+  fn check_terminal(&self, h_state: &Self::HiddenState) -> bool {
+    h_state.payoff().is_some()
+  }
+
+  fn agents(&self) -> Vec<Self::Agent> {
+    vec![Agent(0), Agent(1)]
+  }
+}
+
+impl From<Agent> for u8 {
+  fn from(agent: Agent) -> Self {
+    agent.0
+  }
+}
+
+impl Display for Move {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+impl Display for Observation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:?} ({:?})", self.action, self.card)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+  };
+
+  use lib::utils::Bounds;
+  use mcts::{
+    bandits::{Bandit, Composite, Position, Split, Uct, UniformlyRandomBandit},
+    forest::Node,
+    rollout::RandomRollout,
+    search::{InformationSets, Search},
+    SearchLimit,
+  };
+
+  use super::*;
+
+  fn root_values(belief: Belief, information_sets: InformationSets) -> (f32, f32) {
+    let problem = Arc::new(Kuhn);
+    let search = Search::new(
+      problem,
+      Arc::new(belief),
+      1,
+      SearchLimit::new(20000),
+      Uct(2.0),
+      RandomRollout(10),
+    )
+    .with_information_sets(information_sets);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let policy = search.get_policy();
+    let value = |m| policy.iter().find(|e| e.action == m).unwrap().value;
+    (value(Move::Pass), value(Move::Bet))
+  }
+
+  #[test]
+  fn test_fold_jack() {
+    // calling a bet with the lowest card always loses 2
+    let belief = Belief::new(0, Card::Jack, vec![Move::Pass, Move::Bet]);
+    for information_sets in [
+      InformationSets::SingleObserver,
+      InformationSets::MultipleObserver,
+    ] {
+      let (fold, call) = root_values(belief.clone(), information_sets);
+      assert!((fold + 1.0).abs() < 1e-3 && (call + 2.0).abs() < 1e-3);
+    }
+  }
+
+  #[test]
+  fn test_bet_queen() {
+    // the second player sees its own card in its tree, so a bet with the
+    // queen is called by the king and folded by the jack, losing 0.5 on
+    // average
+    let belief = Belief::new(0, Card::Queen, vec![]);
+    let (check, bet) = root_values(belief.clone(), InformationSets::MultipleObserver);
+    assert!((bet + 0.5).abs() < 0.25, "{bet}");
+    assert!(check > bet);
+
+    // with the first player's information sets, the second player can't
+    // tell its cards apart after the bet, and always calls
+    let (_, bet) = root_values(belief, InformationSets::SingleObserver);
+    assert!(bet.abs() < 0.25, "{bet}");
+  }
+
+  // uniformly random, checking that it's only asked to select at the
+  // positions it was given, and recording them
+  #[derive(Clone, Copy)]
+  struct Checked(Split);
+
+  // (agent, depth) of every selection of a `Checked` bandit
+  static SELECTED_AT: Mutex<Vec<(usize, u32)>> = Mutex::new(vec![]);
+
+  impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Checked {
+    fn select(
+      &self,
+      state: &S,
+      node: &Node<A, O>,
+      available: Option<&BTreeSet<A>>,
+      bounds: &Bounds,
+      position: Position,
+    ) -> A {
+      assert!(self.0.contains(position), "{position:?}");
+      SELECTED_AT
+        .lock()
+        .unwrap()
+        .push((position.agent, position.depth));
+      UniformlyRandomBandit.select(state, node, available, bounds, position)
+    }
+  }
+
+  #[test]
+  fn test_composite_bandit() {
+    // the root, then the first player, then the second one
+    let bandit = Composite(
+      Split::Depth(1),
+      Checked(Split::Depth(1)),
+      Composite(
+        Split::Agent(0),
+        Checked(Split::Agent(0)),
+        Checked(Split::Agent(1)),
+      ),
+    );
+    let search = Search::new(
+      Arc::new(Kuhn),
+      Arc::new(Belief::new(0, Card::Queen, vec![])),
+      1,
+      SearchLimit::new(2000),
+      bandit,
+      RandomRollout(10),
+    );
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    assert_eq!(search.get_policy().len(), 2);
+    // every part of the composite was asked to select
+    let selected_at = SELECTED_AT.lock().unwrap();
+    assert!(selected_at.contains(&(0, 0)));
+    assert!(selected_at
+      .iter()
+      .any(|&(agent, depth)| agent == 0 && depth >= 1));
+    assert!(selected_at
+      .iter()
+      .any(|&(agent, depth)| agent == 1 && depth >= 1));
+  }
+}
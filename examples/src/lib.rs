@@ -1,6 +1,10 @@
+pub mod kingmaker;
+pub mod kuhn;
+pub mod matrix;
+
 use std::{collections::BTreeMap, fmt::Display};
 
-use lib::MctsProblem;
+use lib::{ChanceOutcome, MctsProblem};
 use rand::distributions::{Distribution, WeightedIndex};
 
 type Action = usize;
@@ -92,7 +96,7 @@ impl MctsProblem for StaticPOMDP {
   ) -> Vec<(f32, Self::Observation)> {
     let action_result = &self.states[*h_state].outgoing_actions[action];
     let wi = WeightedIndex::new(&action_result.weights).unwrap();
-    let index = wi.sample(&mut rand::thread_rng());
+    let index = wi.sample(&mut lib::random::rng());
     *h_state = action_result.next_state_id[index];
     vec![(
       action_result.reward[index],
@@ -103,6 +107,31 @@ impl MctsProblem for StaticPOMDP {
     )]
   }
 
+  fn chance_outcomes(
+    &self,
+    h_state: &Self::HiddenState,
+    action: &Self::Action,
+  ) -> Option<Vec<ChanceOutcome<Self::Observation>>> {
+    let action_result = &self.states[*h_state].outgoing_actions[action];
+    let total_w: f32 = action_result.weights.iter().sum();
+    Some(
+      (0..action_result.weights.len())
+        .map(|ix| {
+          (
+            action_result.weights[ix] / total_w,
+            vec![(
+              action_result.reward[ix],
+              Observation {
+                id: action_result.observation_id[ix],
+                action: *action,
+              },
+            )],
+          )
+        })
+        .collect(),
+    )
+  }
+
   fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
     let mut new_dist = vec![0.0; b_state.state_probs.len()];
     for s_i in 0..self.state_count {
@@ -136,7 +165,7 @@ impl MctsProblem for StaticPOMDP {
 
   fn sample_h_state(&self, b_state: &Self::BeliefState) -> Self::HiddenState {
     let wi = WeightedIndex::new(&b_state.state_probs).unwrap();
-    wi.sample(&mut rand::thread_rng())
+    wi.sample(&mut lib::random::rng())
   }
 
   fn check_terminal(&self, h_state: &Self::HiddenState) -> bool {
@@ -189,7 +218,12 @@ pub fn prob1() -> StaticPOMDP {
 }
 
 pub fn prob2() -> StaticPOMDP {
-  let mut m = StaticPOMDP::new(3, 2, 3, vec![1.0, 0.0, 0.0], 1.0);
+  discounted_prob2(1.0)
+}
+
+// prob2 is fully observable, the observation is the id of the next state
+pub fn discounted_prob2(discount: f32) -> StaticPOMDP {
+  let mut m = StaticPOMDP::new(3, 2, 3, vec![1.0, 0.0, 0.0], discount);
 
   m.add_transition(0, 0, 0, 0, 0.0, 0.5);
   m.add_transition(0, 0, 2, 2, 0.0, 0.5);
@@ -211,9 +245,23 @@ pub fn prob2() -> StaticPOMDP {
 }
 #[cfg(test)]
 pub mod tests {
-  use std::{fs::File, sync::Arc};
-
-  use mcts::{bandits::Uct, forest::render::save, search::Search, EmptyInit, SearchLimit};
+  use std::{
+    fs::File,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+  };
+
+  use lib::{ParticleBelief, ParticleProblem};
+  use mcts::{
+    bandits::{Fpu, Puct, Uct, UniformlyRandomBandit},
+    concurrent::ConcurrentSearch,
+    forest::render::save,
+    rollout::RandomRollout,
+    search::{InformationSets, RootNoise, Search, VirtualLoss, Widening},
+    EmptyInit, SearchBackend, SearchLimit,
+  };
+  use rand::Rng;
 
   use super::*;
 
@@ -258,4 +306,576 @@ pub mod tests {
     //println!("{:?}", forest);
     save(&forest, File::create("agent.dot").unwrap(), 500, 5);
   }
+
+  #[test]
+  fn test_stop() {
+    let problem = Arc::new(prob2());
+    let start_state = Arc::new(problem.start_state());
+    let (tx, rx) = mpsc::channel();
+    let search = Search::new(
+      problem.clone(),
+      start_state.clone(),
+      1,
+      SearchLimit::new(u32::MAX),
+      Uct(1.2),
+      EmptyInit,
+    )
+    .with_progress_sender(Duration::from_millis(10), tx);
+    let stop = search.stop_handle();
+    let handle = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(100));
+      stop.stop();
+    });
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    handle.join().unwrap();
+    let info = search.info();
+    assert!(info.select_count > 0);
+    assert!(info.best_action.is_some());
+    assert!(rx.try_iter().count() > 0);
+  }
+
+  #[test]
+  fn test_node_budget() {
+    let problem = Arc::new(prob2());
+    let start_state = Arc::new(problem.start_state());
+    let search = Search::new(
+      problem.clone(),
+      start_state.clone(),
+      1,
+      SearchLimit::new(5000),
+      Uct(1.2),
+      EmptyInit,
+    )
+    .with_node_budget(100);
+    let mut workers = search.create_workers(2);
+    search.start(&mut workers[0]);
+    search.start(&mut workers[1]);
+    let forest = search.forest.read().unwrap();
+    assert!(forest.epoch() > 0);
+    // a block can add at most one node past the budget before compacting
+    assert!(forest.size() <= 101);
+  }
+
+  // action 2 is worth 0.5, action 1 is worth 0 as the observation after it
+  // doesn't reveal which of the two branches the agent is in
+  fn check_prob1<S: SearchBackend<StaticPOMDP> + Sync>(search: &S) {
+    crossbeam::scope(|s| {
+      for _ in 0..4 {
+        s.spawn(|_| {
+          let mut worker = search.create_workers(1);
+          search.start(&mut worker[0]);
+        });
+      }
+    })
+    .unwrap();
+    let policy = search.get_policy();
+    let best = policy
+      .iter()
+      .max_by(|a, b| a.probability.total_cmp(&b.probability))
+      .unwrap();
+    assert_eq!(best.action, 2);
+  }
+
+  #[test]
+  fn test_backends() {
+    let problem = Arc::new(prob1());
+    let start_state = Arc::new(problem.start_state());
+    let limit = SearchLimit::new(4000);
+    check_prob1(&Search::new(
+      problem.clone(),
+      start_state.clone(),
+      1,
+      limit.clone(),
+      Uct(1.2),
+      EmptyInit,
+    ));
+    check_prob1(&ConcurrentSearch::new(
+      problem.clone(),
+      start_state.clone(),
+      2,
+      limit,
+      Uct(1.2),
+      EmptyInit,
+    ));
+  }
+
+  #[test]
+  fn test_virtual_loss() {
+    let problem = Arc::new(prob1());
+    let start_state = Arc::new(problem.start_state());
+    for virtual_loss in [VirtualLoss::Loss(1), VirtualLoss::Visits(3)] {
+      let search = Search::new(
+        problem.clone(),
+        start_state.clone(),
+        8,
+        SearchLimit::new(4000),
+        Uct(1.2),
+        EmptyInit,
+      )
+      .with_virtual_loss(virtual_loss);
+      check_prob1(&search);
+      // nothing is left in flight once the workers return
+      let info = search.info();
+      let root_visits: u32 = info.root_actions.iter().map(|(_, c, _)| c).sum();
+      assert_eq!(root_visits, info.select_count);
+    }
+  }
+
+  // action values of the uniformly random policy, by iterating the bellman
+  // equation of the underlying mdp
+  fn uniform_action_values(m: &StaticPOMDP) -> Vec<Vec<f32>> {
+    let mut v = vec![0.0; m.state_count];
+    let mut q = vec![vec![]; m.state_count];
+    for _ in 0..500 {
+      for (s, def) in m.states.iter().enumerate() {
+        q[s] = def
+          .outgoing_actions
+          .values()
+          .map(|a| {
+            let total: f32 = a.weights.iter().sum();
+            (0..a.weights.len())
+              .map(|i| a.weights[i] / total * (a.reward[i] + m.discount * v[a.next_state_id[i]]))
+              .sum()
+          })
+          .collect();
+      }
+      v = q
+        .iter()
+        .map(|qs| qs.iter().sum::<f32>() / qs.len().max(1) as f32)
+        .collect();
+    }
+    q
+  }
+
+  #[test]
+  fn test_discount() {
+    // the tree and the rollouts both follow the uniformly random policy, so
+    // the action values at the root are unbiased estimates of its values
+    let problem = Arc::new(discounted_prob2(0.9));
+    let expected = uniform_action_values(&problem);
+    let search = Search::new(
+      problem.clone(),
+      Arc::new(problem.start_state()),
+      1,
+      SearchLimit::new(20000),
+      UniformlyRandomBandit,
+      RandomRollout(60),
+    );
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    for entry in search.get_policy() {
+      let error = (entry.value - expected[0][entry.action]).abs();
+      assert!(error < 0.15, "{} vs {:?}", entry.value, expected[0]);
+    }
+  }
+
+  #[test]
+  fn test_chance_nodes() {
+    let problem = Arc::new(discounted_prob2(0.9));
+    let expected = uniform_action_values(&problem);
+    let outcomes = problem.chance_outcomes(&0, &0).unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert!((outcomes.iter().map(|(p, _)| p).sum::<f32>() - 1.0).abs() < 1e-6);
+
+    let search = Search::new(
+      problem.clone(),
+      Arc::new(problem.start_state()),
+      1,
+      SearchLimit::new(5000),
+      UniformlyRandomBandit,
+      RandomRollout(60),
+    )
+    .with_chance_nodes();
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    for entry in search.get_policy() {
+      let error = (entry.value - expected[0][entry.action]).abs();
+      assert!(error < 0.15, "{} vs {:?}", entry.value, expected[0]);
+    }
+
+    // the first action of the start state reaches both outcomes with
+    // probability 0.5, and its value weighs theirs by it rather than by how
+    // often they were sampled
+    let forest = search.forest.read().unwrap();
+    let root = forest.cursor(forest.roots()[0]);
+    let chance = root.chance_outcomes(&0);
+    assert_eq!(chance.len(), 2);
+    assert!(chance.iter().all(|(p, ..)| (p - 0.5).abs() < 1e-6));
+    let weighted: f32 = chance
+      .iter()
+      .map(|(p, r, child)| p * (r + 0.9 * child.value()))
+      .sum();
+    let value = root.action(&0).unwrap().value;
+    assert!((value - weighted).abs() < 1e-4, "{value} vs {weighted}");
+  }
+
+  #[test]
+  fn test_widening() {
+    let problem = Arc::new(prob2());
+    let start_state = Arc::new(problem.start_state());
+    let single = Widening { k: 1.0, alpha: 0.0 };
+
+    // the first action by key order is the only one, as there are no priors
+    let search = Search::new(
+      problem.clone(),
+      start_state.clone(),
+      1,
+      SearchLimit::new(500),
+      Uct(1.2),
+      EmptyInit,
+    )
+    .with_action_widening(single);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    for entry in search.get_policy() {
+      assert_eq!(entry.probability > 0.0, entry.action == 0);
+    }
+
+    // action 0 has two outcomes at the root, only one of them gets a child
+    let search = Search::new(
+      problem.clone(),
+      start_state.clone(),
+      1,
+      SearchLimit::new(500),
+      Uct(1.2),
+      EmptyInit,
+    )
+    .with_observation_widening(single);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let forest = search.forest.read().unwrap();
+    assert_eq!(forest.children(forest.roots()[0]).count(), 2);
+  }
+
+  #[test]
+  fn test_open_loop() {
+    let problem = Arc::new(prob2());
+    let mut b_state = problem.start_state();
+    let mut search = Search::new(
+      problem.clone(),
+      Arc::new(b_state.clone()),
+      1,
+      SearchLimit::new(500),
+      Uct(1.2),
+      EmptyInit,
+    )
+    .with_open_loop();
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let size = {
+      let forest = search.forest.read().unwrap();
+      // the tree only branches on actions
+      assert_eq!(forest.children(forest.roots()[0]).count(), 0);
+      forest.size()
+    };
+
+    // action 1 always leads to state 2, the subtree below it is kept
+    let observation = Observation { id: 2, action: 1 };
+    problem.belief_update(&mut b_state, &observation);
+    search.advance(Arc::new(b_state), &1, &[observation]);
+    assert!(search.forest.read().unwrap().size() < size);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    assert_eq!(search.get_policy().len(), 2);
+  }
+
+  #[test]
+  fn test_advance_limit() {
+    let problem = Arc::new(prob2());
+    let mut b_state = problem.start_state();
+    let mut search = Search::new(
+      problem.clone(),
+      Arc::new(b_state.clone()),
+      1,
+      SearchLimit::new(500),
+      Uct(1.2),
+      EmptyInit,
+    );
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+
+    let observation = Observation { id: 2, action: 1 };
+    problem.belief_update(&mut b_state, &observation);
+    search.advance(Arc::new(b_state), &1, &[observation]);
+    let root_count = |search: &Search<_, _, _>| {
+      let forest = search.forest.read().unwrap();
+      forest.cursor(forest.roots()[0]).select_count()
+    };
+    let inherited = root_count(&search);
+    assert!(inherited > 0);
+    // the selects below the new root don't count towards the limit
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    assert!(root_count(&search) - inherited >= 500);
+  }
+
+  #[test]
+  fn test_restart_budget() {
+    let problem = Arc::new(prob2());
+    let mut search = Search::new(
+      problem.clone(),
+      Arc::new(problem.start_state()),
+      1,
+      SearchLimit::new(500),
+      Uct(1.2),
+      EmptyInit,
+    );
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let searched = search.info().select_count;
+    assert!(searched >= 500);
+
+    // a second search at the same root gets the whole limit again
+    search.restart_budget();
+    assert_eq!(search.info().select_count, 0);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    assert!(search.info().select_count >= 500);
+    let forest = search.forest.read().unwrap();
+    assert!(forest.cursor(forest.roots()[0]).select_count() >= searched + 500);
+  }
+
+  #[test]
+  fn test_particle_belief() {
+    let problem = ParticleProblem::new(prob1(), 200);
+    let mut b_state = problem.start_state();
+    assert!(b_state.particles().iter().all(|s| *s == 0 || *s == 5));
+
+    // both start states observe the same after action 1
+    problem.belief_update(&mut b_state, &(1, Observation { id: 0, action: 1 }));
+    assert_eq!(b_state.len(), 200);
+    assert!(b_state.particles().iter().all(|s| *s == 1 || *s == 6));
+
+    // only state 6 observes 3 after action 3
+    problem.belief_update(&mut b_state, &(3, Observation { id: 3, action: 3 }));
+    assert!(b_state.particles().iter().all(|s| *s == 8));
+
+    let mut depleted = ParticleBelief::new(vec![1]);
+    depleted.reinvigorate(10, Clone::clone);
+    assert_eq!(depleted.len(), 10);
+
+    // no state observes 3 after action 1, the filter falls back to the states
+    // reached by the action
+    let mut b_state = problem.start_state();
+    problem.belief_update(&mut b_state, &(1, Observation { id: 3, action: 1 }));
+    assert_eq!(b_state.len(), 200);
+    assert!(b_state.particles().iter().all(|s| *s == 1 || *s == 6));
+    assert!([1, 6].contains(&problem.sample_h_state(&b_state)));
+  }
+
+  #[test]
+  fn test_search_particles() {
+    let problem = Arc::new(ParticleProblem::new(prob1(), 100));
+    let search = Search::new(
+      problem.clone(),
+      Arc::new(problem.start_state()),
+      1,
+      SearchLimit::new(1000),
+      Uct(2.4),
+      EmptyInit,
+    )
+    .with_particles(50);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let particles = search.particles_after(&1, &[(1, Observation { id: 0, action: 1 })]);
+    assert!(!particles.is_empty() && particles.len() <= 50);
+    assert!(particles.iter().all(|s| *s == 1 || *s == 6));
+  }
+
+  #[test]
+  fn test_subset_armed() {
+    // both states after the first action look the same, but only the second
+    // one allows action 1, which is worth 2 there
+    let mut m = StaticPOMDP::new(4, 2, 1, vec![0.0, 0.0, 0.0, 1.0], 1.0);
+    m.add_transition(3, 0, 0, 0, 0.0, 0.5);
+    m.add_transition(3, 0, 1, 0, 0.0, 0.5);
+    m.add_transition(0, 0, 2, 0, 1.0, 1.0);
+    m.add_transition(1, 0, 2, 0, 0.0, 1.0);
+    m.add_transition(1, 1, 2, 0, 2.0, 1.0);
+    let problem = Arc::new(m);
+    let search = Search::new(
+      problem.clone(),
+      Arc::new(problem.start_state()),
+      1,
+      SearchLimit::new(5000),
+      Uct(1.2),
+      EmptyInit,
+    )
+    .with_information_sets(InformationSets::MultipleObserver);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let value = search.get_policy()[0].value;
+    assert!((value - 1.5).abs() < 0.2, "{value}");
+  }
+
+  #[test]
+  fn test_root_noise() {
+    let problem = Arc::new(prob2());
+    let start_state = Arc::new(problem.start_state());
+    // unvisited actions valued above any return are all tried first
+    let bandit = Puct::new(1.0)
+      .with_fpu(Fpu::Absolute(1e6))
+      .with_growth(19652.0);
+    let search = Search::new(
+      problem,
+      start_state,
+      1,
+      SearchLimit::new(50),
+      bandit,
+      EmptyInit,
+    )
+    .with_root_noise(RootNoise {
+      alpha: 0.3,
+      epsilon: 0.25,
+    });
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let forest = search.forest.read().unwrap();
+    let actions = forest.cursor(forest.roots()[0]).actions();
+    assert!(actions.len() > 1);
+    assert!(actions.iter().all(|a| a.select_count > 0));
+    // the priors are still a distribution, but no longer uniform
+    let total: f32 = actions.iter().map(|a| a.prior).sum();
+    assert!((total - 1.0).abs() < 1e-4);
+    assert!(actions
+      .iter()
+      .any(|a| (a.prior - actions[0].prior).abs() > 1e-4));
+  }
+
+  // select counts and values of the root actions after a search of prob2 with
+  // random selection and rollouts, on a thread of its own
+  fn random_search(seed: Option<u64>, thread_seed: Option<u64>) -> Vec<(u32, f32)> {
+    thread::spawn(move || {
+      if let Some(seed) = thread_seed {
+        lib::random::seed(seed);
+      }
+      let problem = Arc::new(prob2());
+      let start_state = Arc::new(problem.start_state());
+      let mut search = Search::new(
+        problem,
+        start_state,
+        1,
+        SearchLimit::new(2000),
+        UniformlyRandomBandit,
+        RandomRollout(10),
+      );
+      if let Some(seed) = seed {
+        search = search.with_seed(seed);
+      }
+      let mut worker = search.create_workers(1);
+      search.start(&mut worker[0]);
+      let mut policy = search.get_policy();
+      policy.sort_by_key(|e| e.action);
+      policy.iter().map(|e| (e.select_count, e.value)).collect()
+    })
+    .join()
+    .unwrap()
+  }
+
+  #[test]
+  fn test_seeded_search() {
+    // the workers carry their own generators, whatever the thread's
+    let seeded = random_search(Some(7), None);
+    assert_eq!(seeded, random_search(Some(7), Some(1)));
+    assert_ne!(seeded, random_search(Some(8), None));
+    // or the search draws from the thread's generator
+    assert_eq!(random_search(None, Some(7)), random_search(None, Some(7)));
+  }
+
+  #[test]
+  fn test_seeded_concurrent_search() {
+    let search = |seed| {
+      let problem = Arc::new(prob2());
+      let search = ConcurrentSearch::new(
+        problem.clone(),
+        Arc::new(problem.start_state()),
+        1,
+        SearchLimit::new(2000),
+        UniformlyRandomBandit,
+        RandomRollout(10),
+      )
+      .with_seed(seed);
+      let mut worker = search.create_workers(1);
+      search.start(&mut worker[0]);
+      let mut policy = search.get_policy();
+      policy.sort_by_key(|e| e.action);
+      policy
+        .iter()
+        .map(|e| (e.select_count, e.value))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(search(7), search(7));
+    assert_ne!(search(7), search(8));
+  }
+
+  // the first agent flips a coin and wins on heads, the second one has no move
+  struct Coin;
+
+  impl MctsProblem for Coin {
+    type Agent = u8;
+    type Action = usize;
+    type Observation = bool;
+    type BeliefState = Option<bool>;
+    type HiddenState = Option<bool>;
+
+    fn start_state(&self) -> Self::BeliefState {
+      None
+    }
+
+    fn sample_h_state(&self, b_state: &Self::BeliefState) -> Self::HiddenState {
+      *b_state
+    }
+
+    fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
+      *b_state = Some(*obs);
+    }
+
+    fn agent_to_act(&self, _h_state: &Self::HiddenState) -> Self::Agent {
+      0
+    }
+
+    fn legal_actions(&self, h_state: &Self::HiddenState) -> Vec<Self::Action> {
+      if h_state.is_none() {
+        vec![0]
+      } else {
+        vec![]
+      }
+    }
+
+    fn apply_action(
+      &self,
+      h_state: &mut Self::HiddenState,
+      _action: &Self::Action,
+    ) -> Vec<(f32, Self::Observation)> {
+      let heads = lib::random::rng().gen_bool(0.5);
+      *h_state = Some(heads);
+      let reward = if heads { 1.0 } else { -1.0 };
+      vec![(reward, heads), (-reward, heads)]
+    }
+
+    fn check_terminal(&self, h_state: &Self::HiddenState) -> bool {
+      h_state.is_some()
+    }
+
+    fn agents(&self) -> Vec<Self::Agent> {
+      vec![0, 1]
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "deterministic games")]
+  fn test_stochastic_solver() {
+    let search = Search::new(
+      Arc::new(Coin),
+      Arc::new(None),
+      1,
+      SearchLimit::new(100),
+      Uct(1.0),
+      EmptyInit,
+    )
+    .with_solver();
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+  }
 }
@@ -0,0 +1,121 @@
+use lib::SimultaneousMoveProblem;
+
+// a zero sum game of one simultaneous move, the rows are the actions of the
+// first agent, the columns the ones of the second, and the entries its rewards
+pub struct MatrixGame {
+  pub payoffs: Vec<Vec<f32>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Agent(u8);
+
+impl MatrixGame {
+  // rock paper scissors in which winning with rock pays 2, its equilibrium
+  // plays paper half of the time and each of the others a quarter
+  pub fn biased_rock_paper_scissors() -> Self {
+    Self {
+      payoffs: vec![
+        vec![0.0, -1.0, 2.0],
+        vec![1.0, 0.0, -1.0],
+        vec![-2.0, 1.0, 0.0],
+      ],
+    }
+  }
+}
+
+impl SimultaneousMoveProblem for MatrixGame {
+  type Agent = Agent;
+  type Action = usize;
+  // whether the move was played
+  type State = bool;
+
+  fn agents(&self) -> Vec<Self::Agent> {
+    vec![Agent(0), Agent(1)]
+  }
+
+  fn start_state(&self) -> Self::State {
+    false
+  }
+
+  fn check_terminal(&self, state: &Self::State) -> bool {
+    *state
+  }
+
+  fn legal_actions(&self, _state: &Self::State) -> Vec<Vec<Self::Action>> {
+    vec![
+      (0..self.payoffs.len()).collect(),
+      (0..self.payoffs[0].len()).collect(),
+    ]
+  }
+
+  fn apply_joint_action(&self, state: &mut Self::State, actions: &[Self::Action]) -> Vec<f32> {
+    *state = true;
+    let payoff = self.payoffs[actions[0]][actions[1]];
+    vec![payoff, -payoff]
+  }
+}
+
+impl From<Agent> for u8 {
+  fn from(agent: Agent) -> Self {
+    agent.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use lib::{JointBelief, Simultaneous};
+  use mcts::{
+    bandits::{Bandit, Exp3, RegretMatching},
+    rollout::RandomRollout,
+    search::Search,
+    SearchLimit,
+  };
+
+  use super::*;
+
+  type Problem = Simultaneous<MatrixGame>;
+
+  // the payoffs of biased rock paper scissors
+  const RANGE: (f32, f32) = (-2.0, 2.0);
+
+  fn root_policy<B>(bandit: B) -> Vec<f32>
+  where
+    B: Bandit<
+      <Problem as lib::MctsProblem>::HiddenState,
+      usize,
+      <Problem as lib::MctsProblem>::Observation,
+    >,
+  {
+    let problem = Arc::new(Simultaneous(MatrixGame::biased_rock_paper_scissors()));
+    let search = Search::new(
+      problem,
+      Arc::new(JointBelief::new(false)),
+      1,
+      SearchLimit::new(20000),
+      bandit,
+      RandomRollout(1),
+    )
+    .with_seed(0);
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let mut policy = search.get_policy();
+    policy.sort_by_key(|e| e.action);
+    policy.into_iter().map(|e| e.probability).collect()
+  }
+
+  #[test]
+  fn test_mixed_equilibrium() {
+    // the first agent's visits follow the equilibrium at the joint node, up
+    // to the exploration
+    for policy in [
+      root_policy(RegretMatching(0.05, RANGE)),
+      root_policy(Exp3(0.2, RANGE)),
+    ] {
+      for (p, q) in policy.iter().zip([0.25, 0.5, 0.25]) {
+        assert!((p - q).abs() < 0.1, "{policy:?}");
+      }
+    }
+  }
+}
@@ -1,6 +1,14 @@
 pub mod utils;
 
+pub mod random;
 pub mod sat;
 pub mod search;
+pub mod simultaneous;
 
-pub use search::{FullyObservableDeterministicMctsProblem, MctsProblem};
+pub use search::{
+  ChanceOutcome, FullyObservableDeterministicMctsProblem, MctsProblem, ParticleBelief,
+  ParticleProblem,
+};
+pub use simultaneous::{
+  JointBelief, JointObservation, JointState, Simultaneous, SimultaneousMoveProblem,
+};
@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+
+use rand::{rngs::StdRng, Error, RngCore, SeedableRng};
+
+// the generator the searches, the bandits and the problems draw from, in
+// place of `rand::thread_rng`. there's one per thread, seeded by the os until
+// `seed` is called, after which everything drawn on the thread is
+// reproducible. problems should draw from `rng` for the same reason
+thread_local! {
+  static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// handle to the generator of the current thread
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadRng;
+
+pub fn rng() -> ThreadRng {
+  ThreadRng
+}
+
+// reseeds the generator of the current thread
+pub fn seed(seed: u64) {
+  replace(StdRng::seed_from_u64(seed));
+}
+
+// makes `rng` the generator of the current thread, and returns the previous
+// one, so that a search worker can carry its own generator across threads
+pub fn replace(rng: StdRng) -> StdRng {
+  RNG.with(|current| std::mem::replace(&mut *current.borrow_mut(), rng))
+}
+
+// runs `f` with `rng` as the generator of the current thread, and puts the
+// previous one back afterwards, even when `f` panics. `rng` is left where `f`
+// stopped drawing from it
+pub fn with_rng<T>(rng: &mut StdRng, f: impl FnOnce() -> T) -> T {
+  struct Restore<'a>(&'a mut StdRng);
+
+  impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+      RNG.with(|current| std::mem::swap(&mut *current.borrow_mut(), self.0));
+    }
+  }
+
+  RNG.with(|current| std::mem::swap(&mut *current.borrow_mut(), rng));
+  let _restore = Restore(rng);
+  f()
+}
+
+impl RngCore for ThreadRng {
+  fn next_u32(&mut self) -> u32 {
+    RNG.with(|rng| rng.borrow_mut().next_u32())
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    RNG.with(|rng| rng.borrow_mut().next_u64())
+  }
+
+  fn fill_bytes(&mut self, dest: &mut [u8]) {
+    RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+  }
+
+  fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+    RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::panic::{catch_unwind, AssertUnwindSafe};
+
+  use rand::Rng;
+
+  use super::*;
+
+  #[test]
+  fn test_with_rng() {
+    seed(1);
+    let expected: u64 = StdRng::seed_from_u64(1).gen();
+    let mut other = StdRng::seed_from_u64(2);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+      with_rng(&mut other, || {
+        let _: u64 = rng().gen();
+        panic!("drawing failed")
+      })
+    }));
+    assert!(result.is_err());
+    // the generator of the thread is back, and untouched
+    assert_eq!(rng().gen::<u64>(), expected);
+  }
+}
@@ -1,3 +1,11 @@
+use rand::seq::SliceRandom;
+
+use crate::utils::Bounds;
+
+// probability of an outcome of an action, with the reward and observation of
+// every agent
+pub type ChanceOutcome<O> = (f32, Vec<(f32, O)>);
+
 pub trait MctsProblem {
   type Agent: Copy + Into<u8>;
 
@@ -38,6 +46,34 @@ pub trait MctsProblem {
     1.0
   }
 
+  // per step discount of every agent's rewards
+  fn discounts(&self) -> Vec<f32> {
+    vec![self.discount(); self.agents().len()]
+  }
+
+  // identifies states reached through different sequences of observations so
+  // that the search can share their nodes. only sound when the state decides
+  // the node of every agent's tree, as in fully observable problems
+  fn transposition_key(&self, _h_state: &Self::HiddenState) -> Option<u64> {
+    None
+  }
+
+  // bounds of every agent's return from any state, if they are known
+  fn value_bounds(&self) -> Option<Vec<Bounds>> {
+    None
+  }
+
+  // every outcome `apply_action` can have from `h_state`, with its
+  // probability and the reward and observation of every agent, for problems
+  // that can enumerate their chance events. the probabilities sum to 1
+  fn chance_outcomes(
+    &self,
+    _h_state: &Self::HiddenState,
+    _action: &Self::Action,
+  ) -> Option<Vec<ChanceOutcome<Self::Observation>>> {
+    None
+  }
+
   fn sample_h_state_batched(
     &self,
     b_state: &Self::BeliefState,
@@ -78,6 +114,14 @@ pub trait FullyObservableDeterministicMctsProblem {
   fn discount(&self) -> f32 {
     1.0
   }
+  // states with equal hashes are searched as the same node when the search
+  // has transpositions enabled
+  fn state_hash(&self, _state: &Self::State) -> Option<u64> {
+    None
+  }
+  fn value_bounds(&self) -> Option<Vec<Bounds>> {
+    None
+  }
 }
 
 impl<T> MctsProblem for T
@@ -98,6 +142,12 @@ where
   fn discount(&self) -> f32 {
     self.discount()
   }
+  fn transposition_key(&self, h_state: &Self::HiddenState) -> Option<u64> {
+    self.state_hash(h_state)
+  }
+  fn value_bounds(&self) -> Option<Vec<Bounds>> {
+    self.value_bounds()
+  }
   fn legal_actions(&self, h_state: &Self::HiddenState) -> Vec<Self::Action> {
     self.legal_actions(h_state)
   }
@@ -126,3 +176,234 @@ where
     self.agents()
   }
 }
+
+// a belief represented by sampled hidden states, updated by rejection
+// sampling as in POMCP
+#[derive(Clone, Debug)]
+pub struct ParticleBelief<S> {
+  particles: Vec<S>,
+}
+
+impl<S: Clone> ParticleBelief<S> {
+  pub fn new(particles: Vec<S>) -> Self {
+    Self { particles }
+  }
+
+  pub fn particles(&self) -> &[S] {
+    &self.particles
+  }
+
+  pub fn len(&self) -> usize {
+    self.particles.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.particles.is_empty()
+  }
+
+  pub fn sample(&self) -> S {
+    self
+      .particles
+      .choose(&mut crate::random::rng())
+      .expect("the particle filter is depleted")
+      .clone()
+  }
+
+  // simulates `action` from random particles and keeps the states in which
+  // the agent that took it observes `observation`, until there are `count`
+  // of them or `max_attempts` simulations were made
+  pub fn rejection_update<P: MctsProblem<HiddenState = S>>(
+    &self,
+    problem: &P,
+    action: &P::Action,
+    observation: &P::Observation,
+    count: usize,
+    max_attempts: usize,
+  ) -> Self {
+    let mut particles = Vec::with_capacity(count);
+    if self.is_empty() {
+      return Self::new(particles);
+    }
+    for _ in 0..max_attempts {
+      if particles.len() >= count {
+        break;
+      }
+      let mut state = self.sample();
+      let agent = problem.agent_to_act(&state).into() as usize;
+      if problem.apply_action(&mut state, action)[agent].1 == *observation {
+        particles.push(state);
+      }
+    }
+    Self::new(particles)
+  }
+
+  // the states `action` leads to from `count` random particles, whatever they
+  // observe
+  pub fn propagate<P: MctsProblem<HiddenState = S>>(
+    &self,
+    problem: &P,
+    action: &P::Action,
+    count: usize,
+  ) -> Self {
+    if self.is_empty() {
+      return Self::new(vec![]);
+    }
+    let particles = (0..count)
+      .map(|_| {
+        let mut state = self.sample();
+        problem.apply_action(&mut state, action);
+        state
+      })
+      .collect();
+    Self::new(particles)
+  }
+
+  // adds perturbed copies of random particles until there are `count`, so
+  // that a depleted filter doesn't collapse onto a few states
+  pub fn reinvigorate(&mut self, count: usize, mut perturb: impl FnMut(&S) -> S) {
+    if self.is_empty() {
+      return;
+    }
+    while self.particles.len() < count {
+      let particle = perturb(&self.sample());
+      self.particles.push(particle);
+    }
+  }
+}
+
+// moves a particle to a nearby state, to reinvigorate depleted beliefs
+pub type Perturbation<S> = fn(&S) -> S;
+
+// runs any problem on particle beliefs, so that it doesn't need an exact
+// `belief_update`. observations are paired with the action that caused them,
+// as the rejection update needs both
+pub struct ParticleProblem<P: MctsProblem> {
+  pub problem: P,
+  pub particle_count: usize,
+  pub max_attempts: usize,
+  // particles are copied when unset
+  pub perturbation: Option<Perturbation<P::HiddenState>>,
+}
+
+impl<P: MctsProblem> ParticleProblem<P> {
+  pub fn new(problem: P, particle_count: usize) -> Self {
+    Self {
+      problem,
+      particle_count,
+      max_attempts: particle_count * 100,
+      perturbation: None,
+    }
+  }
+
+  pub fn with_perturbation(mut self, perturbation: Perturbation<P::HiddenState>) -> Self {
+    self.perturbation = Some(perturbation);
+    self
+  }
+}
+
+impl<P: MctsProblem> MctsProblem for ParticleProblem<P>
+where
+  P::HiddenState: Clone,
+{
+  type Agent = P::Agent;
+  type Action = P::Action;
+  type Observation = (P::Action, P::Observation);
+  type BeliefState = ParticleBelief<P::HiddenState>;
+  type HiddenState = P::HiddenState;
+
+  fn start_state(&self) -> Self::BeliefState {
+    let b_state = self.problem.start_state();
+    ParticleBelief::new(
+      self
+        .problem
+        .sample_h_state_batched(&b_state, self.particle_count),
+    )
+  }
+
+  fn sample_h_state(&self, b_state: &Self::BeliefState) -> Self::HiddenState {
+    b_state.sample()
+  }
+
+  fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
+    let (action, observation) = obs;
+    let updated = b_state.rejection_update(
+      &self.problem,
+      action,
+      observation,
+      self.particle_count,
+      self.max_attempts,
+    );
+    // when no particle explains the observation, the filter keeps the states
+    // the action leads to from the previous ones rather than running empty
+    *b_state = if updated.is_empty() {
+      b_state.propagate(&self.problem, action, self.particle_count)
+    } else {
+      updated
+    };
+    match self.perturbation {
+      Some(perturb) => b_state.reinvigorate(self.particle_count, perturb),
+      None => b_state.reinvigorate(self.particle_count, Clone::clone),
+    }
+  }
+
+  fn agent_to_act(&self, h_state: &Self::HiddenState) -> Self::Agent {
+    self.problem.agent_to_act(h_state)
+  }
+
+  fn legal_actions(&self, h_state: &Self::HiddenState) -> Vec<Self::Action> {
+    self.problem.legal_actions(h_state)
+  }
+
+  fn apply_action(
+    &self,
+    h_state: &mut Self::HiddenState,
+    action: &Self::Action,
+  ) -> Vec<(f32, Self::Observation)> {
+    self
+      .problem
+      .apply_action(h_state, action)
+      .into_iter()
+      .map(|(r, o)| (r, (action.clone(), o)))
+      .collect()
+  }
+
+  fn check_terminal(&self, h_state: &Self::HiddenState) -> bool {
+    self.problem.check_terminal(h_state)
+  }
+
+  fn agents(&self) -> Vec<Self::Agent> {
+    self.problem.agents()
+  }
+
+  fn discounts(&self) -> Vec<f32> {
+    self.problem.discounts()
+  }
+
+  fn transposition_key(&self, h_state: &Self::HiddenState) -> Option<u64> {
+    self.problem.transposition_key(h_state)
+  }
+
+  fn value_bounds(&self) -> Option<Vec<Bounds>> {
+    self.problem.value_bounds()
+  }
+
+  fn chance_outcomes(
+    &self,
+    h_state: &Self::HiddenState,
+    action: &Self::Action,
+  ) -> Option<Vec<ChanceOutcome<Self::Observation>>> {
+    let outcomes = self.problem.chance_outcomes(h_state, action)?;
+    Some(
+      outcomes
+        .into_iter()
+        .map(|(p, outcome)| {
+          let outcome = outcome
+            .into_iter()
+            .map(|(r, o)| (r, (action.clone(), o)))
+            .collect();
+          (p, outcome)
+        })
+        .collect(),
+    )
+  }
+}
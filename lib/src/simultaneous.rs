@@ -0,0 +1,153 @@
+use rand::seq::SliceRandom;
+
+use crate::search::MctsProblem;
+
+// a problem in which every agent picks an action in each state, and the state
+// changes once all of them did, as in goofspiel or rock paper scissors
+pub trait SimultaneousMoveProblem {
+  type Agent: Copy + Into<u8>;
+  type Action: Clone + Ord;
+  type State: Clone;
+
+  fn agents(&self) -> Vec<Self::Agent>;
+  fn start_state(&self) -> Self::State;
+  fn check_terminal(&self, state: &Self::State) -> bool;
+  // the legal actions of every agent, in the order of `agents`. agents that
+  // don't move in a state have a single action that passes
+  fn legal_actions(&self, state: &Self::State) -> Vec<Vec<Self::Action>>;
+  // rewards of every agent, given the action of every agent
+  fn apply_joint_action(&self, state: &mut Self::State, actions: &[Self::Action]) -> Vec<f32>;
+}
+
+// runs a simultaneous move problem in the search. the agents pick their
+// actions in turn without seeing the picks of the others, and all of them
+// observe the joint action once it's complete. as each agent has its own
+// tree, the agents select at a joint node from their own statistics only,
+// which is decoupled selection: `Uct` becomes decoupled uct, and
+// `Exp3` or `RegretMatching` converge to mixed strategies
+pub struct Simultaneous<P>(pub P);
+
+// a state of the problem, with the actions picked so far in the current step
+#[derive(Clone, Debug)]
+pub struct JointState<S, A> {
+  pub state: S,
+  pub chosen: Vec<A>,
+}
+
+// what an agent knows of a joint state, the picks it didn't see are `None`
+#[derive(Clone, Debug)]
+pub struct JointBelief<S, A> {
+  pub state: S,
+  pub chosen: Vec<Option<A>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum JointObservation<A> {
+  // the agent's own pick
+  Chose(A),
+  // another agent picked
+  Hidden,
+  // the pick of every agent, once the last one picked
+  Joint(Vec<A>),
+}
+
+impl<S, A> JointBelief<S, A> {
+  pub fn new(state: S) -> Self {
+    Self {
+      state,
+      chosen: vec![],
+    }
+  }
+}
+
+impl<P: SimultaneousMoveProblem> MctsProblem for Simultaneous<P> {
+  type Agent = P::Agent;
+  type Action = P::Action;
+  type Observation = JointObservation<P::Action>;
+  type BeliefState = JointBelief<P::State, P::Action>;
+  type HiddenState = JointState<P::State, P::Action>;
+
+  fn start_state(&self) -> Self::BeliefState {
+    JointBelief::new(self.0.start_state())
+  }
+
+  // the picks the belief doesn't know are uniformly random
+  fn sample_h_state(&self, b_state: &Self::BeliefState) -> Self::HiddenState {
+    let legal = self.0.legal_actions(&b_state.state);
+    let chosen = b_state
+      .chosen
+      .iter()
+      .zip(legal)
+      .map(|(pick, actions)| {
+        pick
+          .clone()
+          .unwrap_or_else(|| actions.choose(&mut crate::random::rng()).unwrap().clone())
+      })
+      .collect();
+    JointState {
+      state: b_state.state.clone(),
+      chosen,
+    }
+  }
+
+  fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
+    match obs {
+      JointObservation::Chose(action) => b_state.chosen.push(Some(action.clone())),
+      JointObservation::Hidden => b_state.chosen.push(None),
+      JointObservation::Joint(actions) => {
+        self.0.apply_joint_action(&mut b_state.state, actions);
+        b_state.chosen.clear();
+      }
+    }
+  }
+
+  fn agent_to_act(&self, h_state: &Self::HiddenState) -> Self::Agent {
+    self.0.agents()[h_state.chosen.len()]
+  }
+
+  fn legal_actions(&self, h_state: &Self::HiddenState) -> Vec<Self::Action> {
+    if self.0.check_terminal(&h_state.state) {
+      vec![]
+    } else {
+      self
+        .0
+        .legal_actions(&h_state.state)
+        .swap_remove(h_state.chosen.len())
+    }
+  }
+
+  fn apply_action(
+    &self,
+    h_state: &mut Self::HiddenState,
+    action: &Self::Action,
+  ) -> Vec<(f32, Self::Observation)> {
+    let agents = self.0.agents();
+    let picker = h_state.chosen.len();
+    h_state.chosen.push(action.clone());
+    if h_state.chosen.len() < agents.len() {
+      return (0..agents.len())
+        .map(|ix| {
+          if ix == picker {
+            (0.0, JointObservation::Chose(action.clone()))
+          } else {
+            (0.0, JointObservation::Hidden)
+          }
+        })
+        .collect();
+    }
+    let actions = std::mem::take(&mut h_state.chosen);
+    let rewards = self.0.apply_joint_action(&mut h_state.state, &actions);
+    rewards
+      .into_iter()
+      .map(|r| (r, JointObservation::Joint(actions.clone())))
+      .collect()
+  }
+
+  fn check_terminal(&self, h_state: &Self::HiddenState) -> bool {
+    self.0.check_terminal(&h_state.state)
+  }
+
+  fn agents(&self) -> Vec<Self::Agent> {
+    self.0.agents()
+  }
+}
@@ -1,7 +1,12 @@
+use rand::Rng;
+
+// mean of the samples, and their variance by welford's algorithm
 #[derive(Debug)]
 pub struct RunningAverage {
   mean: f32,
   count: u32,
+  // sum of the squared differences from the mean
+  m2: f32,
 }
 
 impl RunningAverage {
@@ -9,6 +14,7 @@ impl RunningAverage {
     Self {
       mean: 0.0,
       count: 0,
+      m2: 0.0,
     }
   }
 
@@ -20,11 +26,28 @@ impl RunningAverage {
     self.count
   }
 
+  // population variance of the samples, 0 until there are two
+  pub fn variance(&self) -> f32 {
+    if self.count < 2 {
+      0.0
+    } else {
+      (self.m2 / self.count as f32).max(0.0)
+    }
+  }
+
+  // adds `c` samples of value `v`
   pub fn add_sample(&mut self, v: f32, c: u32) {
     let new_c = c + self.count;
-    self.mean += (v - self.mean) * (c as f32) / (new_c as f32);
+    let delta = v - self.mean;
+    self.mean += delta * (c as f32) / (new_c as f32);
+    self.m2 += delta * (v - self.mean) * c as f32;
     self.count = new_c;
   }
+
+  // replaces the mean, keeping the count and the variance
+  pub fn set_value(&mut self, v: f32) {
+    self.mean = v;
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -45,6 +68,14 @@ impl Bounds {
     }
   }
 
+  pub fn low(&self) -> f32 {
+    self.low
+  }
+
+  pub fn high(&self) -> f32 {
+    self.high
+  }
+
   pub fn normalise(&self, v: f32) -> f32 {
     if self.low >= self.high {
       0.0
@@ -53,6 +84,13 @@ impl Bounds {
     }
   }
 
+  pub fn merge(&mut self, other: &Bounds) {
+    if other.low <= other.high {
+      self.update_bounds(other.low);
+      self.update_bounds(other.high);
+    }
+  }
+
   pub fn update_bounds(&mut self, v: f32) {
     if self.low > self.high {
       self.low = v;
@@ -67,3 +105,61 @@ impl Bounds {
     }
   }
 }
+
+// a sample of the symmetric dirichlet distribution with `n` categories, from
+// normalised gamma samples
+pub fn sample_dirichlet(alpha: f32, n: usize, rng: &mut impl Rng) -> Vec<f32> {
+  let mut sample: Vec<_> = (0..n).map(|_| sample_gamma(alpha, rng)).collect();
+  let total: f32 = sample.iter().sum();
+  if total > 0.0 {
+    sample.iter_mut().for_each(|x| *x /= total);
+  } else {
+    sample.iter_mut().for_each(|x| *x = 1.0 / n as f32);
+  }
+  sample
+}
+
+// marsaglia and tsang's method, shapes below 1 are boosted by a uniform power
+pub fn sample_gamma(shape: f32, rng: &mut impl Rng) -> f32 {
+  if shape < 1.0 {
+    let u: f32 = rng.gen();
+    return sample_gamma(shape + 1.0, rng) * u.powf(1.0 / shape);
+  }
+  let d = shape - 1.0 / 3.0;
+  let c = 1.0 / (9.0 * d).sqrt();
+  loop {
+    let x = sample_normal(rng);
+    let v = (1.0 + c * x).powi(3);
+    if v <= 0.0 {
+      continue;
+    }
+    let u: f32 = 1.0 - rng.gen::<f32>();
+    if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+      return d * v;
+    }
+  }
+}
+
+pub fn sample_beta(a: f32, b: f32, rng: &mut impl Rng) -> f32 {
+  let x = sample_gamma(a, rng);
+  let y = sample_gamma(b, rng);
+  if x + y > 0.0 {
+    x / (x + y)
+  } else {
+    0.5
+  }
+}
+
+// standard gumbel, by inverting its distribution function
+pub fn sample_gumbel(rng: &mut impl Rng) -> f32 {
+  // in (0, 1), for both logarithms to be finite
+  let u = rng.gen::<f32>().max(f32::EPSILON);
+  -(-u.ln()).ln()
+}
+
+// standard normal, by the box muller transform
+pub fn sample_normal(rng: &mut impl Rng) -> f32 {
+  let u: f32 = 1.0 - rng.gen::<f32>();
+  let v: f32 = rng.gen();
+  (-2.0 * u.ln()).sqrt() * (2.0 * std::f32::consts::PI * v).cos()
+}
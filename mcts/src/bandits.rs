@@ -1,11 +1,71 @@
-use lib::utils::Bounds;
+use std::collections::BTreeSet;
+
+use lib::utils::{sample_beta, sample_normal, Bounds};
 use rand::seq::{IteratorRandom, SliceRandom};
 
-use crate::forest::Node;
+use crate::forest::{ActionInfo, Node};
 
 pub trait Bandit<S, A, O>: Copy {
-  // state is an argument to allow agent/state specific bandit policies
-  fn select(&self, state: &S, node: &Node<A, O>, bounds: &Bounds) -> A;
+  // state is an argument to allow agent/state specific bandit policies.
+  // `available` restricts the selection to the actions legal in the state,
+  // for searches whose nodes are reached by states with different legal
+  // actions, see `search::InformationSets`
+  fn select(
+    &self,
+    state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    position: Position,
+  ) -> A;
+
+  // the search only gathers AMAF statistics for bandits that read them
+  fn needs_amaf(&self) -> bool {
+    false
+  }
+
+  // probability of `select` picking `action`, for the bandits that sample.
+  // the search asks right after selecting, and passes it back to `update`
+  fn probability(
+    &self,
+    _node: &Node<A, O>,
+    _action: &A,
+    _available: Option<&BTreeSet<A>>,
+    _position: Position,
+  ) -> Option<f32> {
+    None
+  }
+
+  // called when the return `value` of a simulation that selected `action` is
+  // backpropagated to `node`, for bandits that keep their own statistics
+  fn update(
+    &self,
+    _node: &mut Node<A, O>,
+    _action: &A,
+    _value: f32,
+    _selection: &Selection<A>,
+    _bounds: &Bounds,
+    _position: Position,
+  ) {
+  }
+}
+
+// how the action being updated was selected
+#[derive(Clone, Debug)]
+pub struct Selection<A> {
+  // see `Bandit::probability`, none for the actions the bandit didn't select
+  pub probability: Option<f32>,
+  // the actions that were available, kept along with the probability
+  pub available: Option<BTreeSet<A>>,
+}
+
+// where the node of a selection or an update is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+  // index of the agent to act, whose tree the node is in
+  pub agent: usize,
+  // number of actions played from the root to the node
+  pub depth: u32,
 }
 
 #[derive(Copy, Clone)]
@@ -14,37 +74,152 @@ pub struct UniformlyRandomBandit;
 #[derive(Copy, Clone)]
 pub struct Uct(pub f32);
 
+// the exploration constant c is multiplied by the prior and the square root
+// of the parent's select count, see `Puct::with_growth` and `Fpu` for the
+// alphazero options
 #[derive(Copy, Clone)]
-pub struct Puct(pub f32);
+pub struct Puct {
+  pub c: f32,
+  pub fpu: Fpu,
+  // c_base of the growth of the exploration constant, when set
+  pub growth: Option<f32>,
+}
+
+// first play urgency, the value given to the actions that weren't selected
+// yet
+#[derive(Copy, Clone, Debug)]
+pub enum Fpu {
+  // the normalised value of the parent minus the reduction
+  ParentValue(f32),
+  // a fixed value, normalised by the bounds
+  Absolute(f32),
+  // the lower bound
+  Loss,
+}
 
 #[derive(Copy, Clone)]
 pub struct GreedyBandit;
 
-impl<S, A: Clone, O> Bandit<S, A, O> for UniformlyRandomBandit {
-  fn select(&self, _state: &S, node: &Node<A, O>, _bounds: &Bounds) -> A {
+// exp3 with the exploration rate gamma, which samples actions with
+// probabilities exponential in their importance weighted returns. the returns
+// are normalised by the fixed (low, high) range, the bounds of the search
+// change as it goes and would weigh the returns differently over time. at the
+// joint nodes of `lib::Simultaneous` its visits approach a mixed equilibrium
+#[derive(Copy, Clone)]
+pub struct Exp3(pub f32, pub (f32, f32));
+
+// regret matching with the exploration rate gamma, which samples actions
+// proportionally to their positive regrets, estimated from the sampled return
+// as in outcome sampling. the returns are normalised as in `Exp3`
+#[derive(Copy, Clone)]
+pub struct RegretMatching(pub f32, pub (f32, f32));
+
+// ucb1-tuned, uct with the exploration of each action scaled by an upper
+// bound of its variance, at most 1/4
+#[derive(Copy, Clone)]
+pub struct Ucb1Tuned;
+
+// ucb-v with the constants c and zeta, exploring by the empirical variance and
+// a correction for its estimation error
+#[derive(Copy, Clone)]
+pub struct UcbV(pub f32, pub f32);
+
+// kl-ucb with the constant c, the index of an action is the largest mean whose
+// divergence from the empirical one is at most (ln n + c ln ln n) / n_a
+#[derive(Copy, Clone)]
+pub struct KlUcb(pub Divergence, pub f32);
+
+#[derive(Copy, Clone, Debug)]
+pub enum Divergence {
+  // of bernoulli distributions, for returns normalised to [0, 1]
+  Bernoulli,
+  // of gaussians with the given variance of the normalised returns
+  Gaussian(f32),
+}
+
+// thompson sampling, selects the action with the highest mean sampled from
+// its posterior
+#[derive(Copy, Clone)]
+pub struct Thompson(pub Posterior);
+
+#[derive(Copy, Clone, Debug)]
+pub enum Posterior {
+  // the normalised returns count as fractional successes of a Beta(1, 1) prior
+  Beta,
+  // gaussian around the mean, with the empirical variance shrunk towards the
+  // largest one of returns in [0, 1]
+  Gaussian,
+}
+
+// selects with the first bandit at the positions in the split and with the
+// second one elsewhere, e.g. one policy at the root and another below it.
+// composites nest, to dispatch between more than two bandits
+#[derive(Copy, Clone)]
+pub struct Composite<F, S>(pub Split, pub F, pub S);
+
+// the positions the first bandit of a `Composite` selects at
+#[derive(Copy, Clone, Debug)]
+pub enum Split {
+  // in the tree of the agent with this index
+  Agent(usize),
+  // at depths below this one, the root only for 1
+  Depth(u32),
+  Custom(fn(Position) -> bool),
+}
+
+// uct with the values blended with the AMAF values, weighted by the schedule
+#[derive(Copy, Clone)]
+pub struct Rave(pub f32, pub RaveSchedule);
+
+// weight of the AMAF value, given the select count n and the AMAF count m
+#[derive(Copy, Clone)]
+pub enum RaveSchedule {
+  // sqrt(k / (3n + k)), equal weights at n = k
+  Equivalence(f32),
+  // m / (n + m + 4 b^2 n m), b being the bias of the AMAF values
+  MinimumMse(f32),
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for UniformlyRandomBandit {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    _bounds: &Bounds,
+    _position: Position,
+  ) -> A {
     node
-      .actions
-      .keys()
-      .choose(&mut rand::thread_rng())
+      .candidate_actions(available)
+      .map(|(a, _)| a)
+      .choose(&mut lib::random::rng())
       .map(|k| k.clone())
       .unwrap()
   }
 }
 
-impl<S, A: Clone, O> Bandit<S, A, O> for Uct {
-  fn select(&self, _state: &S, node: &Node<A, O>, bounds: &Bounds) -> A {
-    let ln_n = (node.select_count() as f32).ln();
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Uct {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
     let mut best_s = f32::MIN;
     let mut best_a = None;
-    let mut actions: Vec<_> = node.actions.iter().collect();
-    actions.shuffle(&mut rand::thread_rng());
+    let mut actions: Vec<_> = node.candidate_actions(available).collect();
+    actions.shuffle(&mut lib::random::rng());
     for (a, data) in actions {
-      let n = data.select_count();
+      let n = data.virtual_select_count();
       if n == 0 {
         return a.clone();
       }
+      // virtual visits can make the actions look visited before the node is
+      let ln_n = (node.parent_count(data).max(1) as f32).ln();
       let exploration_score = (ln_n / n as f32).sqrt();
-      let score = bounds.normalise(data.value()) + self.0 * exploration_score;
+      let score = data.normalised_value(bounds) + self.0 * exploration_score;
       if score > best_s {
         best_s = score;
         best_a = Some(a);
@@ -54,15 +229,69 @@ impl<S, A: Clone, O> Bandit<S, A, O> for Uct {
   }
 }
 
-impl<S, A: Clone, O> Bandit<S, A, O> for Puct {
-  fn select(&self, _state: &S, node: &Node<A, O>, bounds: &Bounds) -> A {
-    let sqrt_sum = (node.select_count() as f32).sqrt();
+impl Puct {
+  // unvisited actions are valued at 0, same as empty averages
+  pub fn new(c: f32) -> Self {
+    Self {
+      c,
+      fpu: Fpu::Absolute(0.0),
+      growth: None,
+    }
+  }
+
+  pub fn with_fpu(mut self, fpu: Fpu) -> Self {
+    self.fpu = fpu;
+    self
+  }
+
+  // the exploration constant grows as c + ln((1 + n + c_base) / c_base) with
+  // the parent's select count n, as in alphazero
+  pub fn with_growth(mut self, c_base: f32) -> Self {
+    self.growth = Some(c_base);
+    self
+  }
+
+  fn exploration(&self, n: u32) -> f32 {
+    match self.growth {
+      Some(c_base) => self.c + ((1.0 + n as f32 + c_base) / c_base).ln(),
+      None => self.c,
+    }
+  }
+}
+
+impl Fpu {
+  fn value<A, O>(&self, node: &Node<A, O>, bounds: &Bounds) -> f32 {
+    match *self {
+      Fpu::ParentValue(reduction) => bounds.normalise(node.value.value()) - reduction,
+      Fpu::Absolute(v) => bounds.normalise(v),
+      Fpu::Loss => 0.0,
+    }
+  }
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Puct {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
     let mut best_s = f32::MIN;
     let mut best_a = None;
-    for (a, data) in node.actions.iter() {
+    let fpu = self.fpu.value(node, bounds);
+    for (a, data) in node.candidate_actions(available) {
+      let parent_count = node.parent_count(data);
+      let sqrt_sum = (parent_count as f32).sqrt();
       let exploration_score =
-        data.static_policy_score * sqrt_sum / (1 + data.select_count()) as f32;
-      let score = bounds.normalise(data.value()) + self.0 * exploration_score;
+        data.static_policy_score * sqrt_sum / (1 + data.virtual_select_count()) as f32;
+      let value = if data.virtual_select_count() == 0 {
+        fpu
+      } else {
+        data.normalised_value(bounds)
+      };
+      let score = value + self.exploration(parent_count) * exploration_score;
       if score > best_s {
         best_s = score;
         best_a = Some(a);
@@ -72,12 +301,367 @@ impl<S, A: Clone, O> Bandit<S, A, O> for Puct {
   }
 }
 
-impl<S, A: Clone, O> Bandit<S, A, O> for GreedyBandit {
-  fn select(&self, _state: &S, node: &Node<A, O>, bounds: &Bounds) -> A {
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for GreedyBandit {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
     let mut best_s = 0;
     let mut best_a = None;
-    for (a, data) in node.actions.iter() {
-      let score = data.select_count();
+    for (a, data) in node.candidate_actions(available) {
+      let score = data.virtual_select_count();
+      if score > best_s {
+        best_s = score;
+        best_a = Some(a);
+      }
+    }
+    best_a.unwrap().clone()
+  }
+}
+
+// samples from actions paired with their probabilities
+fn sample<'a, A>(probabilities: &[(&'a A, f32)]) -> &'a A {
+  probabilities
+    .choose_weighted(&mut lib::random::rng(), |(_, p)| *p)
+    .unwrap()
+    .0
+}
+
+// probability of the action in the sampled probabilities
+fn probability_of<A: Eq>(probabilities: &[(&A, f32)], action: &A) -> Option<f32> {
+  probabilities
+    .iter()
+    .find(|(a, _)| *a == action)
+    .map(|(_, p)| *p)
+}
+
+// the return normalised to [0, 1] by the range
+fn normalise((low, high): (f32, f32), value: f32) -> f32 {
+  ((value - low) / (high - low)).clamp(0.0, 1.0)
+}
+
+// the probabilities mixed with the uniform distribution, by gamma
+fn explore<A>(gamma: f32, weights: Vec<(A, f32)>) -> Vec<(A, f32)> {
+  let k = weights.len() as f32;
+  let total: f32 = weights.iter().map(|(_, w)| w).sum();
+  weights
+    .into_iter()
+    .map(|(a, w)| {
+      let p = if total > 0.0 { w / total } else { 1.0 / k };
+      (a, (1.0 - gamma) * p + gamma / k)
+    })
+    .collect()
+}
+
+impl Exp3 {
+  fn probabilities<'a, A: Ord, O>(
+    &self,
+    node: &'a Node<A, O>,
+    available: Option<&'a BTreeSet<A>>,
+  ) -> Vec<(&'a A, f32)> {
+    let actions: Vec<_> = node.candidate_actions(available).collect();
+    let eta = self.0 / actions.len() as f32;
+    // shifted by the largest estimate, for the exponentials not to overflow
+    let max = actions
+      .iter()
+      .map(|(_, data)| data.cumulative)
+      .fold(f32::MIN, f32::max);
+    let weights = actions
+      .into_iter()
+      .map(|(a, data)| (a, (eta * (data.cumulative - max)).exp()))
+      .collect();
+    explore(self.0, weights)
+  }
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Exp3 {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    _bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    sample(&self.probabilities(node, available)).clone()
+  }
+
+  fn probability(
+    &self,
+    node: &Node<A, O>,
+    action: &A,
+    available: Option<&BTreeSet<A>>,
+    _position: Position,
+  ) -> Option<f32> {
+    probability_of(&self.probabilities(node, available), action)
+  }
+
+  fn update(
+    &self,
+    node: &mut Node<A, O>,
+    action: &A,
+    value: f32,
+    selection: &Selection<A>,
+    _bounds: &Bounds,
+    _position: Position,
+  ) {
+    if let Some(p) = selection.probability {
+      node.actions.get_mut(action).unwrap().cumulative += normalise(self.1, value) / p;
+    }
+  }
+}
+
+impl RegretMatching {
+  fn probabilities<'a, A: Ord, O>(
+    &self,
+    node: &'a Node<A, O>,
+    available: Option<&'a BTreeSet<A>>,
+  ) -> Vec<(&'a A, f32)> {
+    let weights = node
+      .candidate_actions(available)
+      .map(|(a, data)| (a, data.cumulative.max(0.0)))
+      .collect();
+    explore(self.0, weights)
+  }
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for RegretMatching {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    _bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    sample(&self.probabilities(node, available)).clone()
+  }
+
+  fn probability(
+    &self,
+    node: &Node<A, O>,
+    action: &A,
+    available: Option<&BTreeSet<A>>,
+    _position: Position,
+  ) -> Option<f32> {
+    probability_of(&self.probabilities(node, available), action)
+  }
+
+  fn update(
+    &self,
+    node: &mut Node<A, O>,
+    action: &A,
+    value: f32,
+    selection: &Selection<A>,
+    _bounds: &Bounds,
+    _position: Position,
+  ) {
+    let Some(p) = selection.probability else {
+      return;
+    };
+    let v = normalise(self.1, value);
+    // the sampled return is the estimated value of the strategy, and of the
+    // selected action once divided by its probability. the regrets of the
+    // actions that weren't available are left as they are
+    let available = selection.available.as_ref();
+    for (a, data) in node.actions.iter_mut() {
+      if available.is_none_or(|available| available.contains(a)) {
+        let estimate = if a == action { v / p } else { 0.0 };
+        data.cumulative += estimate - v;
+      }
+    }
+  }
+}
+
+// the action with the highest score, unvisited actions first. the score is
+// given the normalised value and the visits of the action and of the parent
+fn select_by_index<A: Clone + Ord, O>(
+  node: &Node<A, O>,
+  available: Option<&BTreeSet<A>>,
+  bounds: &Bounds,
+  index: impl Fn(&ActionInfo, f32, u32, u32) -> f32,
+) -> A {
+  let mut best_s = f32::MIN;
+  let mut best_a = None;
+  let mut actions: Vec<_> = node.candidate_actions(available).collect();
+  actions.shuffle(&mut lib::random::rng());
+  for (a, data) in actions {
+    let n = data.virtual_select_count();
+    if n == 0 {
+      return a.clone();
+    }
+    let parent_count = node.parent_count(data).max(1);
+    let score = index(data, data.normalised_value(bounds), n, parent_count);
+    if score > best_s {
+      best_s = score;
+      best_a = Some(a);
+    }
+  }
+  best_a.unwrap().clone()
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Ucb1Tuned {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    select_by_index(node, available, bounds, |data, value, n, parent| {
+      let ln_n = (parent as f32).ln();
+      let n = n as f32;
+      let variance = data.normalised_variance(bounds) + (2.0 * ln_n / n).sqrt();
+      value + (ln_n / n * variance.min(0.25)).sqrt()
+    })
+  }
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for UcbV {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    let UcbV(c, zeta) = *self;
+    select_by_index(node, available, bounds, |data, value, n, parent| {
+      let e = zeta * (parent as f32).ln();
+      let n = n as f32;
+      value + (2.0 * data.normalised_variance(bounds) * e / n).sqrt() + c * 3.0 * e / n
+    })
+  }
+}
+
+impl Divergence {
+  // largest q above p with a divergence from p of at most d
+  fn upper_bound(&self, p: f32, d: f32) -> f32 {
+    match *self {
+      Divergence::Gaussian(variance) => p + (2.0 * variance * d).sqrt(),
+      Divergence::Bernoulli => {
+        let p = p.clamp(0.0, 1.0);
+        let (mut low, mut high) = (p, 1.0);
+        for _ in 0..16 {
+          let q = (low + high) / 2.0;
+          if bernoulli_kl(p, q) > d {
+            high = q;
+          } else {
+            low = q;
+          }
+        }
+        low
+      }
+    }
+  }
+}
+
+fn bernoulli_kl(p: f32, q: f32) -> f32 {
+  let eps = 1e-6;
+  let p = p.clamp(eps, 1.0 - eps);
+  let q = q.clamp(eps, 1.0 - eps);
+  p * (p / q).ln() + (1.0 - p) * ((1.0 - p) / (1.0 - q)).ln()
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for KlUcb {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    let KlUcb(divergence, c) = *self;
+    select_by_index(node, available, bounds, |_, value, n, parent| {
+      let ln_n = (parent as f32).ln();
+      let budget = ln_n + c * ln_n.max(1.0).ln();
+      divergence.upper_bound(value, budget / n as f32)
+    })
+  }
+}
+
+impl Posterior {
+  fn sample(&self, data: &ActionInfo, bounds: &Bounds) -> f32 {
+    let mut rng = lib::random::rng();
+    let n = data.returns.count() as f32;
+    let value = data.normalised_value(bounds).clamp(0.0, 1.0);
+    match self {
+      Posterior::Beta => sample_beta(1.0 + n * value, 1.0 + n * (1.0 - value), &mut rng),
+      Posterior::Gaussian => {
+        let (mean, variance) = if n == 0.0 {
+          (0.5, 0.25)
+        } else {
+          let v = (n * data.normalised_variance(bounds) + 0.25) / (n + 1.0);
+          (value, v / (n + 1.0))
+        };
+        mean + variance.sqrt() * sample_normal(&mut rng)
+      }
+    }
+  }
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Thompson {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    node
+      .candidate_actions(available)
+      .map(|(a, data)| (a, self.0.sample(data, bounds)))
+      .max_by(|(_, x), (_, y)| x.total_cmp(y))
+      .map(|(a, _)| a.clone())
+      .unwrap()
+  }
+}
+
+impl RaveSchedule {
+  fn beta(&self, n: u32, m: u32) -> f32 {
+    if m == 0 {
+      return 0.0;
+    }
+    let (n, m) = (n as f32, m as f32);
+    match self {
+      RaveSchedule::Equivalence(k) => (k / (3.0 * n + k)).sqrt(),
+      RaveSchedule::MinimumMse(b) => m / (n + m + 4.0 * b * b * n * m),
+    }
+  }
+}
+
+impl<S, A: Clone + Ord, O> Bandit<S, A, O> for Rave {
+  fn select(
+    &self,
+    _state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    _position: Position,
+  ) -> A {
+    let mut best_s = f32::MIN;
+    let mut best_a = None;
+    let mut actions: Vec<_> = node.candidate_actions(available).collect();
+    actions.shuffle(&mut lib::random::rng());
+    for (a, data) in actions {
+      let ln_n = (node.parent_count(data).max(1) as f32).ln();
+      let n = data.virtual_select_count();
+      let m = data.amaf.count();
+      if n == 0 && m == 0 {
+        return a.clone();
+      }
+      let beta = self.1.beta(n, m);
+      let value =
+        (1.0 - beta) * data.normalised_value(bounds) + beta * bounds.normalise(data.amaf.value());
+      let score = value + self.0 * (ln_n / n.max(1) as f32).sqrt();
       if score > best_s {
         best_s = score;
         best_a = Some(a);
@@ -85,4 +669,115 @@ impl<S, A: Clone, O> Bandit<S, A, O> for GreedyBandit {
     }
     best_a.unwrap().clone()
   }
+
+  fn needs_amaf(&self) -> bool {
+    true
+  }
+}
+
+impl Split {
+  pub fn contains(&self, position: Position) -> bool {
+    match *self {
+      Split::Agent(agent) => position.agent == agent,
+      Split::Depth(depth) => position.depth < depth,
+      Split::Custom(f) => f(position),
+    }
+  }
+}
+
+impl<S, A, O, F, G> Bandit<S, A, O> for Composite<F, G>
+where
+  F: Bandit<S, A, O>,
+  G: Bandit<S, A, O>,
+{
+  fn select(
+    &self,
+    state: &S,
+    node: &Node<A, O>,
+    available: Option<&BTreeSet<A>>,
+    bounds: &Bounds,
+    position: Position,
+  ) -> A {
+    if self.0.contains(position) {
+      self.1.select(state, node, available, bounds, position)
+    } else {
+      self.2.select(state, node, available, bounds, position)
+    }
+  }
+
+  fn needs_amaf(&self) -> bool {
+    self.1.needs_amaf() || self.2.needs_amaf()
+  }
+
+  fn probability(
+    &self,
+    node: &Node<A, O>,
+    action: &A,
+    available: Option<&BTreeSet<A>>,
+    position: Position,
+  ) -> Option<f32> {
+    if self.0.contains(position) {
+      self.1.probability(node, action, available, position)
+    } else {
+      self.2.probability(node, action, available, position)
+    }
+  }
+
+  fn update(
+    &self,
+    node: &mut Node<A, O>,
+    action: &A,
+    value: f32,
+    selection: &Selection<A>,
+    bounds: &Bounds,
+    position: Position,
+  ) {
+    if self.0.contains(position) {
+      self
+        .1
+        .update(node, action, value, selection, bounds, position)
+    } else {
+      self
+        .2
+        .update(node, action, value, selection, bounds, position)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeSet;
+
+  use lib::utils::Bounds;
+
+  use super::{Bandit, Exp3, Position, RegretMatching, Selection};
+  use crate::forest::Node;
+
+  #[test]
+  fn test_sampled_updates() {
+    let mut node: Node<u8, u8> = Node::new();
+    node.create_actions(vec![0, 1, 2]);
+    let position = Position { agent: 0, depth: 0 };
+    let bounds = Bounds::new();
+    // action 2 wasn't legal when 0 was sampled with probability 0.25
+    let selection = Selection {
+      probability: Some(0.25),
+      available: Some(BTreeSet::from([0, 1])),
+    };
+    let bandit = RegretMatching(0.1, (-1.0, 1.0));
+    Bandit::<(), _, _>::update(&bandit, &mut node, &0, 0.0, &selection, &bounds, position);
+    let cumulative = |node: &Node<u8, u8>, a| node.actions[&a].cumulative;
+    assert_eq!(cumulative(&node, 0), 1.5);
+    assert_eq!(cumulative(&node, 1), -0.5);
+    assert_eq!(cumulative(&node, 2), 0.0);
+
+    let mut node: Node<u8, u8> = Node::new();
+    node.create_actions(vec![0, 1]);
+    let bandit = Exp3(0.1, (0.0, 2.0));
+    Bandit::<(), _, _>::update(&bandit, &mut node, &1, 1.0, &selection, &bounds, position);
+    assert_eq!(cumulative(&node, 1), 2.0);
+    // the returns are clamped to the range
+    Bandit::<(), _, _>::update(&bandit, &mut node, &0, 5.0, &selection, &bounds, position);
+    assert_eq!(cumulative(&node, 0), 4.0);
+  }
 }
@@ -0,0 +1,522 @@
+/*
+an alternative to `Search` where every node carries its own locks, instead of the
+whole forest being behind one RwLock.
+
+every node has
+  children behind a RwLock, created on demand with double checked locking
+  statistics behind a RwLock, in the same `forest::Node` type as the arena so the
+  bandits can be shared between both implementations
+  an atomic select count, so that limits can be checked without locking
+
+worker ->
+  loop:
+    for each (node, state) in flight:
+      if state is terminal, queue the trajectory for backprop and restart it
+      if node has no action info, queue the (node, state) for expansion and restart it
+      read lock node and select action for (node, state) using tree policy
+    apply batch apply_action to all (state, action) pairs
+    if the expansion queue is full:
+      apply batch_estimator to all states in it, and obtain pi and v
+    for every queued backprop: write lock each node on the trajectory in turn
+    for every queued expansion:
+      write lock the node and create action info (if no other worker did)
+      backprop v, update the node's static policy estimates with pi
+    for each (node, state, action) in flight:
+      write lock node, increment select count of node and action
+      get_or_create child of node for the observation
+    merge the score bounds seen in this block into the shared ones
+
+no lock is held while another one is taken, so there is no lock ordering to get wrong.
+compaction, tree reuse and the options of `Search` are not supported
+*/
+
+use std::{
+  collections::BTreeMap,
+  sync::{
+    atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    Arc, OnceLock, RwLock,
+  },
+  time::Instant,
+};
+
+use lib::{random, utils::Bounds, MctsProblem};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+  bandits::{Bandit, Position},
+  forest,
+  search::{StopHandle, C_SCALE, C_VISIT},
+  Expansion, PolicyEntry, SearchBackend, SearchLimit, SearchProgress,
+};
+
+pub struct Node<A, O> {
+  children: RwLock<BTreeMap<O, Arc<Node<A, O>>>>,
+  // `stats.children` is unused, the children are kept above
+  stats: RwLock<forest::Node<A, O>>,
+  // mirrors `stats.select_count()`
+  select_count: AtomicU32,
+}
+
+pub struct ConcurrentSearch<P: MctsProblem, B, E> {
+  problem: Arc<P>,
+  b_state: Arc<P::BeliefState>,
+  roots: Vec<Arc<Node<P::Action, P::Observation>>>,
+  block_size: u32,
+  limit: SearchLimit,
+  bandit_policy: B,
+  static_estimator: E,
+  score_bounds: RwLock<Vec<Bounds>>,
+  root_agent: usize,
+  started: OnceLock<Instant>,
+  max_depth: AtomicU32,
+  node_count: AtomicUsize,
+  stop: StopHandle,
+  // seeds the generators of the workers, see `with_seed`
+  seed: Option<u64>,
+  workers_created: AtomicU64,
+}
+
+pub struct Worker<S, A, O> {
+  states_in_flight: Vec<S>,
+  trajectories_in_flight: Vec<Trajectory<A, O>>,
+
+  states_awaiting_expansion: Vec<S>,
+  trajectories_awaiting_expansion: Vec<Trajectory<A, O>>,
+
+  trajectories_awaiting_backprop: Vec<Trajectory<A, O>>,
+
+  // the worker's own generator, in seeded searches
+  rng: Option<StdRng>,
+}
+
+pub struct Trajectory<A, O> {
+  // one node in each player's tree
+  current_: Vec<Arc<Node<A, O>>>,
+
+  // node, emitted reward and the selected action (along with the index of agent)
+  branch: Vec<BranchEntry<A, O>>,
+}
+
+type BranchEntry<A, O> = (Vec<(Arc<Node<A, O>>, f32)>, (usize, A));
+
+impl<A, O> Node<A, O> {
+  fn new() -> Self {
+    Self {
+      children: RwLock::new(BTreeMap::new()),
+      stats: RwLock::new(forest::Node::new()),
+      select_count: AtomicU32::new(0),
+    }
+  }
+}
+
+impl<A, O: Ord + Clone> Node<A, O> {
+  fn get_child(&self, o: &O, node_count: &AtomicUsize) -> Arc<Self> {
+    let guard = self.children.read().unwrap();
+    if !guard.contains_key(o) {
+      drop(guard);
+      let mut lock = self.children.write().unwrap();
+      // we need to check once again if the child hasn't been created by a
+      // competing thread
+      if !lock.contains_key(o) {
+        let result = Arc::new(Node::new());
+        lock.insert(o.clone(), result.clone());
+        node_count.fetch_add(1, Ordering::Relaxed);
+        result
+      } else {
+        lock[o].clone()
+      }
+    } else {
+      guard[o].clone()
+    }
+  }
+}
+
+impl<A: Clone, O> Clone for Trajectory<A, O> {
+  fn clone(&self) -> Self {
+    Trajectory {
+      current_: self.current_.clone(),
+      branch: self.branch.clone(),
+    }
+  }
+}
+
+impl<P: MctsProblem, B, E> ConcurrentSearch<P, B, E>
+where
+  B: Bandit<P::HiddenState, P::Action, P::Observation>,
+  P::HiddenState: Clone,
+  E: Expansion<P>,
+{
+  pub fn new(
+    problem: Arc<P>,
+    b_state: Arc<P::BeliefState>,
+    block_size: u32,
+    limit: SearchLimit,
+    bandit_policy: B,
+    node_init: E,
+  ) -> Self {
+    let agent_count = problem.agents().len();
+    let roots = (0..agent_count).map(|_| Arc::new(Node::new())).collect();
+    let root_agent = problem
+      .agent_to_act(&problem.sample_h_state(&b_state))
+      .into() as usize;
+    let score_bounds = problem
+      .value_bounds()
+      .unwrap_or_else(|| vec![Bounds::new(); agent_count]);
+
+    ConcurrentSearch {
+      problem,
+      b_state,
+      roots,
+      block_size,
+      limit,
+      bandit_policy,
+      static_estimator: node_init,
+      score_bounds: RwLock::new(score_bounds),
+      root_agent,
+      started: OnceLock::new(),
+      max_depth: AtomicU32::new(0),
+      node_count: AtomicUsize::new(agent_count),
+      stop: StopHandle::new(),
+      seed: None,
+      workers_created: AtomicU64::new(0),
+    }
+  }
+
+  // gives every worker its own generator, the n-th worker created by the
+  // search being seeded with seed + n, as in `Search::with_seed`
+  pub fn with_seed(mut self, seed: u64) -> Self {
+    self.seed = Some(seed);
+    self
+  }
+
+  pub fn start(&self, worker: &mut Worker<P::HiddenState, P::Action, P::Observation>) {
+    let mut rng = worker.rng.take();
+    Self::seeded(&mut rng, || self.run(worker));
+    worker.rng = rng;
+  }
+
+  // runs `f` with `rng`, if any, as the generator of the thread
+  fn seeded<T>(rng: &mut Option<StdRng>, f: impl FnOnce() -> T) -> T {
+    match rng {
+      Some(rng) => random::with_rng(rng, f),
+      None => f(),
+    }
+  }
+
+  fn run(&self, worker: &mut Worker<P::HiddenState, P::Action, P::Observation>) {
+    let started = *self.started.get_or_init(Instant::now);
+    // initialize root node if needed
+    for (state, trajectory) in worker
+      .states_in_flight
+      .iter()
+      .zip(worker.trajectories_in_flight.iter())
+    {
+      let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+      let mut stats = trajectory.current_[current_agent_ix].stats.write().unwrap();
+      if !stats.actions_created() {
+        stats.create_actions(self.problem.legal_actions(state));
+        let (_, p) = self.static_estimator.expand(&self.problem, state);
+        for (a, pa) in p {
+          stats.actions.get_mut(&a).unwrap().static_policy_score = pa;
+        }
+      }
+    }
+
+    let agent_count = self.roots.len();
+    loop {
+      // check if search budget remains
+      if self.stop.is_stopped() || !self.limit.more(&self.progress(started)) {
+        return;
+      }
+
+      // select actions
+      let bounds = self.score_bounds.read().unwrap().clone();
+      let agents_and_actions: Vec<_> = worker
+        .trajectories_in_flight
+        .iter_mut()
+        .zip(worker.states_in_flight.iter_mut())
+        .map(|(trajectory, state)| {
+          if self.problem.check_terminal(state) {
+            worker
+              .trajectories_awaiting_backprop
+              .push(trajectory.clone());
+            *state = self.problem.sample_h_state(&self.b_state);
+            self.restart_trajectory(trajectory);
+          }
+          // its guaranteed that the state is not terminal
+          let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+          if !trajectory.current_[current_agent_ix]
+            .stats
+            .read()
+            .unwrap()
+            .actions_created()
+          {
+            worker.states_awaiting_expansion.push(state.clone());
+            worker
+              .trajectories_awaiting_expansion
+              .push(trajectory.clone());
+
+            *state = self.problem.sample_h_state(&self.b_state);
+            self.restart_trajectory(trajectory);
+          }
+
+          let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+          let stats = trajectory.current_[current_agent_ix].stats.read().unwrap();
+          let position = Position {
+            agent: current_agent_ix,
+            depth: trajectory.branch.len() as u32,
+          };
+          (
+            current_agent_ix,
+            self
+              .bandit_policy
+              .select(state, &stats, None, &bounds[current_agent_ix], position),
+          )
+        })
+        .collect();
+      let (agents, actions): (Vec<_>, Vec<_>) = agents_and_actions.into_iter().unzip();
+
+      // apply_actions
+      let outcomes = self
+        .problem
+        .apply_action_batched(&mut worker.states_in_flight, &actions);
+
+      let expansion_result = if worker.states_awaiting_expansion.len() >= self.block_size as usize {
+        Some(
+          self
+            .static_estimator
+            .block_expand(&self.problem, &worker.states_awaiting_expansion),
+        )
+      } else {
+        None
+      };
+
+      // bounds seen in this block, merged into the shared ones at the end
+      let mut local_bounds = vec![Bounds::new(); agent_count];
+
+      for trajectory in worker.trajectories_awaiting_backprop.iter() {
+        self.backpropogate(
+          &mut local_bounds,
+          trajectory,
+          vec![0.0; trajectory.current_.len()],
+        );
+      }
+      worker.trajectories_awaiting_backprop.clear();
+
+      // process expansion queue
+      for (state, trajectory) in worker
+        .states_awaiting_expansion
+        .iter()
+        .zip(worker.trajectories_awaiting_expansion.iter())
+      {
+        let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+        let mut stats = trajectory.current_[current_agent_ix].stats.write().unwrap();
+        if !stats.actions_created() {
+          stats.create_actions(self.problem.legal_actions(state));
+        }
+      }
+
+      if let Some((v, p)) = expansion_result {
+        for ((state, trajectory), (value, static_policy)) in worker
+          .states_awaiting_expansion
+          .iter()
+          .zip(worker.trajectories_awaiting_expansion.iter())
+          .zip(v.into_iter().zip(p))
+        {
+          self.backpropogate(&mut local_bounds, trajectory, value);
+          let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+          let mut stats = trajectory.current_[current_agent_ix].stats.write().unwrap();
+          for (a, pa) in static_policy {
+            stats.actions.get_mut(&a).unwrap().static_policy_score = pa;
+          }
+        }
+        worker.trajectories_awaiting_expansion.clear();
+        worker.states_awaiting_expansion.clear();
+      }
+
+      // descend
+      for ((trajectory, (action, agent_ix)), outcomes_and_rewards) in worker
+        .trajectories_in_flight
+        .iter_mut()
+        .zip(actions.into_iter().zip(agents))
+        .zip(outcomes)
+      {
+        let mut children = Vec::with_capacity(trajectory.current_.len());
+        let mut branch_entry = Vec::with_capacity(trajectory.current_.len());
+        for (ix, node) in trajectory.current_.iter().enumerate() {
+          {
+            let mut stats = node.stats.write().unwrap();
+            stats.increment_select_count();
+            if ix == agent_ix {
+              stats
+                .actions
+                .get_mut(&action)
+                .unwrap()
+                .increment_select_count();
+            }
+          }
+          node.select_count.fetch_add(1, Ordering::Relaxed);
+          children.push(node.get_child(&outcomes_and_rewards[ix].1, &self.node_count));
+          branch_entry.push((node.clone(), outcomes_and_rewards[ix].0));
+        }
+        trajectory.current_ = children;
+        trajectory.branch.push((branch_entry, (agent_ix, action)));
+        self
+          .max_depth
+          .fetch_max(trajectory.branch.len() as u32, Ordering::Relaxed);
+      }
+
+      let mut bounds_guard = self.score_bounds.write().unwrap();
+      for (shared, local) in bounds_guard.iter_mut().zip(local_bounds.iter()) {
+        shared.merge(local);
+      }
+    }
+  }
+
+  fn backpropogate(
+    &self,
+    bounds: &mut [Bounds],
+    trajectory: &Trajectory<P::Action, P::Observation>,
+    mut values: Vec<f32>,
+  ) {
+    for (ix, node) in trajectory.current_.iter().enumerate() {
+      node.stats.write().unwrap().value.add_sample(values[ix], 1);
+      bounds[ix].update_bounds(values[ix]);
+    }
+    let discounts = self.problem.discounts();
+    for (nodes, (agent, action)) in trajectory.branch.iter().rev() {
+      for ix in 0..nodes.len() {
+        let mut stats = nodes[ix].0.stats.write().unwrap();
+        if ix == *agent {
+          let data = stats.actions.get_mut(action).unwrap();
+          data.action_reward.add_sample(nodes[ix].1, 1);
+          data
+            .value_of_next_state
+            .add_sample(discounts[ix] * values[ix], 1);
+        }
+
+        values[ix] = nodes[ix].1 + discounts[ix] * values[ix];
+        stats.value.add_sample(values[ix], 1);
+        bounds[ix].update_bounds(values[ix]);
+      }
+    }
+  }
+
+  pub fn create_workers(
+    &self,
+    count: usize,
+  ) -> Vec<Worker<P::HiddenState, P::Action, P::Observation>> {
+    let mut result = Vec::with_capacity(count);
+    for _ in 0..count {
+      let mut rng = self.seed.map(|seed| {
+        let n = self.workers_created.fetch_add(1, Ordering::Relaxed);
+        StdRng::seed_from_u64(seed.wrapping_add(n))
+      });
+      let states_in_flight = Self::seeded(&mut rng, || {
+        self
+          .problem
+          .sample_h_state_batched(&self.b_state, self.block_size as usize)
+      });
+      result.push(Worker {
+        states_in_flight,
+        trajectories_in_flight: vec![self.empty_trajectory(); self.block_size as usize],
+        trajectories_awaiting_expansion: vec![],
+        states_awaiting_expansion: vec![],
+        trajectories_awaiting_backprop: vec![],
+        rng,
+      });
+    }
+    result
+  }
+
+  pub fn get_policy(&self) -> Vec<PolicyEntry<P::Action>> {
+    let root = self.roots[self.root_agent].stats.read().unwrap();
+    let root_s_count = root.select_count() as f32;
+    let improved = root.improved_policy(C_VISIT, C_SCALE);
+    root
+      .actions
+      .iter()
+      .zip(improved)
+      .map(|((a, data), improved)| PolicyEntry {
+        action: a.clone(),
+        probability: data.select_count() as f32 / root_s_count,
+        select_count: data.select_count(),
+        value: data.value(),
+        improved,
+        proof: None,
+      })
+      .collect()
+  }
+
+  pub fn stop_handle(&self) -> StopHandle {
+    self.stop.clone()
+  }
+
+  fn progress(&self, started: Instant) -> SearchProgress {
+    let mut top_counts = (0, 0);
+    let (mut robust_value, mut max_value) = (f32::MIN, f32::MIN);
+    for data in self.roots[self.root_agent]
+      .stats
+      .read()
+      .unwrap()
+      .actions
+      .values()
+    {
+      let c = data.select_count();
+      if c > top_counts.0 {
+        top_counts = (c, top_counts.0);
+        robust_value = data.value();
+      } else if c > top_counts.1 {
+        top_counts.1 = c;
+      }
+      if c > 0 {
+        max_value = max_value.max(data.value());
+      }
+    }
+    SearchProgress {
+      select_count: self.roots[0].select_count.load(Ordering::Relaxed),
+      forest_size: self.node_count.load(Ordering::Relaxed),
+      max_depth: self.max_depth.load(Ordering::Relaxed),
+      elapsed: started.elapsed(),
+      top_counts,
+      max_robust: top_counts.0 > 0 && robust_value >= max_value,
+    }
+  }
+
+  fn empty_trajectory(&self) -> Trajectory<P::Action, P::Observation> {
+    Trajectory {
+      current_: self.roots.clone(),
+      branch: vec![],
+    }
+  }
+
+  fn restart_trajectory(&self, trajectory: &mut Trajectory<P::Action, P::Observation>) {
+    trajectory.current_ = self.roots.clone();
+    trajectory.branch = vec![];
+  }
+}
+
+impl<P: MctsProblem, B, E> SearchBackend<P> for ConcurrentSearch<P, B, E>
+where
+  B: Bandit<P::HiddenState, P::Action, P::Observation>,
+  P::HiddenState: Clone,
+  E: Expansion<P>,
+{
+  type Worker = Worker<P::HiddenState, P::Action, P::Observation>;
+
+  fn create_workers(&self, count: usize) -> Vec<Self::Worker> {
+    self.create_workers(count)
+  }
+
+  fn start(&self, worker: &mut Self::Worker) {
+    self.start(worker)
+  }
+
+  fn get_policy(&self) -> Vec<PolicyEntry<P::Action>> {
+    self.get_policy()
+  }
+
+  fn stop_handle(&self) -> StopHandle {
+    self.stop_handle()
+  }
+}
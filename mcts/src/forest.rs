@@ -1,16 +1,25 @@
 use std::{
-  collections::BTreeMap,
+  collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
   fmt::{Debug, Display},
+  mem,
+  sync::atomic::{AtomicU32, Ordering},
 };
 
-use lib::utils::RunningAverage;
+use lib::utils::{Bounds, RunningAverage};
+
+use crate::solver::{prove, Outcome, Proof};
+pub mod cursor;
 pub mod render;
 
 // an arena based tree
-// does not support deletion of nodes
+// nodes are only deleted by compacting the whole arena, which
+// invalidates every NodeId held outside the forest
+// with transpositions enabled, children reached through different paths
+// but with the same state key are shared, making each tree a DAG. the depth
+// is part of the key, so the graph never has cycles
 
-#[derive(Clone, Copy, Debug)]
-pub struct NodeId(usize);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(pub(crate) usize);
 
 #[derive(Debug)]
 pub struct Node<A, O> {
@@ -18,16 +27,68 @@ pub struct Node<A, O> {
   pub(crate) actions: BTreeMap<A, ActionInfo>,
   // index to children
   children: BTreeMap<O, NodeId>,
+  // children of open loop searches, keyed by the action taken instead of the
+  // observation received
+  open_children: BTreeMap<A, NodeId>,
   pub(crate) value: RunningAverage,
   select_count: u32,
+  // state key the node was created for, when transpositions are enabled
+  key: Option<u64>,
+  // set by the solver once the result for the agent to act is known
+  pub(crate) proof: Option<Proof>,
+  // pessimistic and optimistic return of every agent under optimal play, set
+  // by score bounded search
+  pub(crate) value_bounds: Option<Vec<Bounds>>,
+  // only the actions ranked below this are selected, see `search::Widening`
+  pub(crate) widened: u32,
+  // values of the opponents replying in the rounds whose first opponent move
+  // is at the node, see `search::Backup::BestReply`
+  pub(crate) repliers: Vec<RunningAverage>,
 }
 
 #[derive(Debug)]
 pub(crate) struct ActionInfo {
   pub(crate) action_reward: RunningAverage,
   pub(crate) value_of_next_state: RunningAverage,
+  // samples of the return of the action, kept for their variance
+  pub(crate) returns: RunningAverage,
+  // all moves as first, returns from the node of the simulations in which
+  // the agent to act played this action at any later point
+  pub(crate) amaf: RunningAverage,
   select_count: u32,
   pub(crate) static_policy_score: f32,
+  // added by trajectories that selected this action and haven't been
+  // backpropagated yet, see `search::VirtualLoss`
+  virtual_visits: AtomicU32,
+  virtual_losses: AtomicU32,
+  // number of visits of the node in which this action was legal, tracked by
+  // information set searches only
+  available: AtomicU32,
+  pub(crate) proof: Option<Proof>,
+  // same as for nodes, the declared bounds of the problem when unset
+  pub(crate) value_bounds: Option<Vec<Bounds>>,
+  // the agent to act is guaranteed more by another action
+  pruned: bool,
+  // position in the order of the static policy scores
+  rank: u32,
+  // number of children first reached through this action
+  pub(crate) outcomes: u32,
+  // the children reached through this action
+  pub(crate) children: Vec<NodeId>,
+  // children reached through this action with the probability and expected
+  // reward of reaching them, when the search has chance nodes enabled
+  pub(crate) chance: Vec<ChanceEdge>,
+  // statistic kept by the bandits that update their own, the importance
+  // weighted reward of `bandits::Exp3` or the regret of
+  // `bandits::RegretMatching`
+  pub(crate) cumulative: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChanceEdge {
+  pub(crate) child: NodeId,
+  pub(crate) probability: f32,
+  pub(crate) reward: f32,
 }
 
 #[derive(Debug)]
@@ -37,20 +98,39 @@ pub struct Forest<A, O> {
 
   // the order doesn't change
   roots: Vec<NodeId>,
+
+  epoch: u32,
+
+  // (tree, depth, state key) of every keyed node
+  transpositions: Option<HashMap<(usize, u32, u64), NodeId>>,
 }
 
 impl<A, O> Forest<A, O>
 where
   // todo remove debug
+  A: Ord + Clone,
   O: Ord + Clone,
 {
   pub fn new(capacity: usize) -> Self {
     Self {
       nodes: Vec::with_capacity(capacity),
       roots: Vec::new(),
+      epoch: 0,
+      transpositions: None,
+    }
+  }
+
+  pub fn enable_transpositions(&mut self) {
+    if self.transpositions.is_none() {
+      self.transpositions = Some(HashMap::new());
+      self.index_transpositions();
     }
   }
 
+  pub fn transpositions_enabled(&self) -> bool {
+    self.transpositions.is_some()
+  }
+
   pub fn new_root(&mut self) -> NodeId {
     let r = self.new_node();
     self.roots.push(r);
@@ -61,6 +141,10 @@ where
     self.roots.clone()
   }
 
+  pub fn size(&self) -> usize {
+    self.nodes.len()
+  }
+
   fn new_node(&mut self) -> NodeId {
     let id = self.nodes.len();
     self.nodes.push(Node::new());
@@ -68,6 +152,153 @@ where
     NodeId(id)
   }
 
+  // moves every root to its child for the corresponding observation, and
+  // drops the nodes that are no longer reachable. returns the same as
+  // `compact`
+  pub fn advance_roots(&mut self, observations: &[O]) -> Vec<Option<NodeId>> {
+    debug_assert_eq!(observations.len(), self.roots.len());
+    self.roots = self
+      .roots()
+      .into_iter()
+      .zip(observations)
+      .map(|(r, o)| self.get_id_of_child(r, o))
+      .collect();
+    self.compact(0)
+  }
+
+  // same as `advance_roots`, for the trees of open loop searches
+  pub fn advance_roots_by_action(&mut self, action: &A) -> Vec<Option<NodeId>> {
+    self.roots = self
+      .roots()
+      .into_iter()
+      .map(|r| self.get_id_of_open_child(r, action))
+      .collect();
+    self.compact(0)
+  }
+
+  // rebuilds the arena with only the nodes reachable from the roots through
+  // children selected at least `min_select_count` times. returns the new id
+  // of every old node that was kept
+  pub fn compact(&mut self, min_select_count: u32) -> Vec<Option<NodeId>> {
+    let (remap, order) = self.reachable(min_select_count);
+
+    let mut old: Vec<_> = mem::take(&mut self.nodes).into_iter().map(Some).collect();
+    self.nodes = Vec::with_capacity(order.len());
+    for id in order {
+      let mut node = old[id.0].take().unwrap();
+      node.children = node
+        .children
+        .into_iter()
+        .filter_map(|(o, c)| remap[c.0].map(|c| (o, c)))
+        .collect();
+      node.open_children = node
+        .open_children
+        .into_iter()
+        .filter_map(|(a, c)| remap[c.0].map(|c| (a, c)))
+        .collect();
+      // every trajectory in flight is dropped along with the old ids
+      for data in node.actions.values_mut() {
+        data.clear_virtual();
+        data.chance = data
+          .chance
+          .iter()
+          .filter_map(|e| remap[e.child.0].map(|child| ChanceEdge { child, ..*e }))
+          .collect();
+        data.children = data.children.iter().filter_map(|c| remap[c.0]).collect();
+      }
+      self.nodes.push(node);
+    }
+    for r in self.roots.iter_mut() {
+      *r = remap[r.0].unwrap();
+    }
+    // depths change when the roots are advanced
+    self.index_transpositions();
+    self.epoch += 1;
+    remap
+  }
+
+  fn index_transpositions(&mut self) {
+    let Some(table) = self.transpositions.as_mut() else {
+      return;
+    };
+    table.clear();
+    let mut seen = vec![false; self.nodes.len()];
+    for (tree, r) in self.roots.iter().enumerate() {
+      let mut queue = VecDeque::from([(*r, 0)]);
+      seen[r.0] = true;
+      while let Some((id, depth)) = queue.pop_front() {
+        if let Some(key) = self.nodes[id.0].key {
+          table.insert((tree, depth, key), id);
+        }
+        for c in self.nodes[id.0].child_ids() {
+          if !seen[c.0] {
+            seen[c.0] = true;
+            queue.push_back((*c, depth + 1));
+          }
+        }
+      }
+    }
+  }
+
+  // number of nodes that would survive `compact(min_select_count)`
+  pub fn retained_size(&self, min_select_count: u32) -> usize {
+    self.reachable(min_select_count).1.len()
+  }
+
+  fn reachable(&self, min_select_count: u32) -> (Vec<Option<NodeId>>, Vec<NodeId>) {
+    let mut remap = vec![None; self.nodes.len()];
+    let mut order = vec![];
+    let mut queue = VecDeque::new();
+    for r in self.roots.iter() {
+      if remap[r.0].is_none() {
+        remap[r.0] = Some(NodeId(order.len()));
+        order.push(*r);
+        queue.push_back(*r);
+      }
+    }
+    while let Some(id) = queue.pop_front() {
+      for c in self.nodes[id.0].child_ids() {
+        if remap[c.0].is_none() && self.nodes[c.0].select_count >= min_select_count {
+          remap[c.0] = Some(NodeId(order.len()));
+          order.push(*c);
+          queue.push_back(*c);
+        }
+      }
+    }
+    (remap, order)
+  }
+
+  // incremented by every compaction, NodeIds from an older epoch are invalid
+  pub fn epoch(&self) -> u32 {
+    self.epoch
+  }
+
+  // observations along the path of most selected children, see
+  // `NodeCursor::principal_variation`
+  pub fn principal_variation(&self, node_id: NodeId) -> Vec<O> {
+    self
+      .cursor(node_id)
+      .principal_variation()
+      .into_iter()
+      .filter_map(|step| step.observation)
+      .collect()
+  }
+
+  pub fn children(&self, node_id: NodeId) -> impl Iterator<Item = (&O, NodeId)> {
+    self.nodes[node_id.0].children.iter().map(|(o, c)| (o, *c))
+  }
+
+  // the existing child of a node, reached by `o` or in open loop trees by
+  // `action`
+  pub(crate) fn find_child(&self, node_id: NodeId, o: &O, action: &A) -> Option<NodeId> {
+    let node = &self.nodes[node_id.0];
+    node
+      .children
+      .get(o)
+      .or_else(|| node.open_children.get(action))
+      .copied()
+  }
+
   pub(crate) fn get_id_of_child(&mut self, node_id: NodeId, o: &O) -> NodeId {
     //print!("fetching child {} of {}:", o, node_id.0);
     if !self.nodes[node_id.0].children.contains_key(o) {
@@ -83,17 +314,68 @@ where
       r
     }
   }
+
+  pub(crate) fn get_id_of_open_child(&mut self, node_id: NodeId, action: &A) -> NodeId {
+    if let Some(r) = self.nodes[node_id.0].open_children.get(action) {
+      return *r;
+    }
+    let r = self.new_node();
+    self.nodes[node_id.0]
+      .open_children
+      .insert(action.clone(), r);
+    r
+  }
+
+  // same as `get_id_of_child`, but a new child is linked to an existing node
+  // of the same tree with the same key at the same depth, if there's one
+  pub(crate) fn get_id_of_transposition(
+    &mut self,
+    node_id: NodeId,
+    o: &O,
+    tree: usize,
+    depth: u32,
+    key: u64,
+  ) -> NodeId {
+    if let Some(r) = self.nodes[node_id.0].children.get(o) {
+      return *r;
+    }
+    let table = self
+      .transpositions
+      .as_ref()
+      .expect("transpositions are not enabled");
+    let child = match table.get(&(tree, depth, key)) {
+      Some(r) => *r,
+      None => {
+        let r = self.new_node();
+        self.nodes[r.0].key = Some(key);
+        self
+          .transpositions
+          .as_mut()
+          .unwrap()
+          .insert((tree, depth, key), r);
+        r
+      }
+    };
+    self.nodes[node_id.0].children.insert(o.clone(), child);
+    child
+  }
 }
 
 impl<A, O> Node<A, O> {
-  fn new() -> Self {
+  pub(crate) fn new() -> Self {
     Self {
       //id: 0,
       actions_created: false,
       actions: BTreeMap::new(),
       children: BTreeMap::new(),
+      open_children: BTreeMap::new(),
       select_count: 0,
       value: RunningAverage::new(),
+      key: None,
+      proof: None,
+      value_bounds: None,
+      widened: u32::MAX,
+      repliers: vec![],
     }
   }
   pub(crate) fn select_count(&self) -> u32 {
@@ -104,9 +386,59 @@ impl<A, O> Node<A, O> {
     self.select_count += 1;
   }
 
+  fn child_ids(&self) -> impl Iterator<Item = &NodeId> {
+    self.children.values().chain(self.open_children.values())
+  }
+
   pub(crate) fn actions_created(&self) -> bool {
     self.actions_created
   }
+
+  // number of visits of the node in which the action could be selected,
+  // which is every visit unless the search tracks availability
+  pub(crate) fn parent_count(&self, data: &ActionInfo) -> u32 {
+    match data.available.load(Ordering::Relaxed) {
+      0 => self.select_count,
+      n => n,
+    }
+  }
+
+  // orders the actions by their static policy scores, ties in key order
+  pub(crate) fn rank_actions(&mut self) {
+    let mut order: Vec<_> = self.actions.values_mut().collect();
+    order.sort_by(|a, b| b.static_policy_score.total_cmp(&a.static_policy_score));
+    for (rank, data) in order.into_iter().enumerate() {
+      data.rank = rank as u32;
+    }
+  }
+
+  pub(crate) fn update_proof(&mut self) {
+    self.proof = prove(self.actions.values().map(|data| data.proof.as_ref()));
+  }
+
+  // recomputes the bounds of the node from the bounds of its actions, and
+  // prunes the actions that can't be better for `agent` than what another one
+  // guarantees
+  pub(crate) fn update_value_bounds(&mut self, agent: usize, declared: &[Bounds]) {
+    let pessimistic = self
+      .actions
+      .values()
+      .map(|data| data.value_bounds.as_deref().unwrap_or(declared)[agent].low())
+      .fold(f32::MIN, f32::max);
+    let mut result = vec![Bounds::new(); declared.len()];
+    for data in self.actions.values_mut() {
+      let bounds = data.value_bounds.as_deref().unwrap_or(declared);
+      // ties go to the actions that guarantee the value
+      let b = &bounds[agent];
+      data.pruned = b.high() < pessimistic || (b.high() == pessimistic && b.low() < pessimistic);
+      if !data.pruned {
+        result.iter_mut().zip(bounds).for_each(|(r, b)| r.merge(b));
+      }
+    }
+    // the other agents can get anything the agent to act may choose
+    result[agent] = Bounds::new_known(pessimistic, result[agent].high());
+    self.value_bounds = Some(result);
+  }
 }
 
 impl<A: Ord, O> Forest<A, O> {
@@ -119,20 +451,147 @@ impl<A: Ord, O> Forest<A, O> {
   }
 }
 
+impl<A, O: Ord> Node<A, O> {
+  pub(crate) fn has_child(&self, o: &O) -> bool {
+    self.children.contains_key(o)
+  }
+}
+
 impl<A: Ord, O> Node<A, O> {
+  // the values of the actions completed as in gumbel muzero. unvisited ones
+  // get the value of the node mixed with the prior weighted values of the
+  // visited ones, then all are rescaled to [0, 1] and multiplied by
+  // (c_visit + most visits) c_scale, to be added to the logits of the priors
+  pub(crate) fn completed_values(&self, c_visit: f32, c_scale: f32) -> Vec<f32> {
+    let total: u32 = self.actions.values().map(|d| d.select_count).sum();
+    let (weight, weighted) =
+      self
+        .actions
+        .values()
+        .filter(|d| d.select_count > 0)
+        .fold((0.0, 0.0), |(w, v), d| {
+          (
+            w + d.static_policy_score,
+            v + d.static_policy_score * d.value(),
+          )
+        });
+    let mixed = if weight > 0.0 {
+      (self.value.value() + total as f32 * weighted / weight) / (total + 1) as f32
+    } else {
+      self.value.value()
+    };
+    let values: Vec<_> = self
+      .actions
+      .values()
+      .map(|d| if d.select_count > 0 { d.value() } else { mixed })
+      .collect();
+    let low = values.iter().cloned().fold(f32::MAX, f32::min);
+    let high = values.iter().cloned().fold(f32::MIN, f32::max);
+    let most = self.actions.values().map(|d| d.select_count).max();
+    let scale = (c_visit + most.unwrap_or(0) as f32) * c_scale;
+    values
+      .into_iter()
+      .map(|v| {
+        if high > low {
+          scale * (v - low) / (high - low)
+        } else {
+          0.0
+        }
+      })
+      .collect()
+  }
+
+  // softmax of the logits of the priors plus the completed values, the policy
+  // target of gumbel muzero. in the order of `actions`
+  pub(crate) fn improved_policy(&self, c_visit: f32, c_scale: f32) -> Vec<f32> {
+    let scores: Vec<_> = self
+      .actions
+      .values()
+      .zip(self.completed_values(c_visit, c_scale))
+      .map(|(d, q)| d.logit() + q)
+      .collect();
+    let max = scores.iter().cloned().fold(f32::MIN, f32::max);
+    let exps: Vec<_> = scores.iter().map(|s| (s - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / total).collect()
+  }
+
+  // the widened actions that aren't proven losses or pruned, among the
+  // `available` ones if given. falls back to all such actions when every
+  // widened one is excluded, and to all actions when every one is
+  pub(crate) fn candidate_actions<'a>(
+    &'a self,
+    available: Option<&'a BTreeSet<A>>,
+  ) -> impl Iterator<Item = (&'a A, &'a ActionInfo)> {
+    let widened = self.widened;
+    let allowed = move |data: &ActionInfo| data.rank < widened && !data.is_excluded();
+    let actions = move || {
+      self
+        .actions
+        .iter()
+        .filter(move |(a, _)| available.is_none_or(|s| s.contains(a)))
+    };
+    let level = if actions().any(|(_, data)| allowed(data)) {
+      2
+    } else if !actions().all(|(_, data)| data.is_excluded()) {
+      1
+    } else {
+      0
+    };
+    actions().filter(move |(_, data)| match level {
+      2 => allowed(data),
+      1 => !data.is_excluded(),
+      _ => true,
+    })
+  }
+
   pub(crate) fn create_actions(&mut self, actions: Vec<A>) {
     //println!("Creating actions on node:{}", self.id);
     debug_assert!(!self.actions_created, "recreating actions");
     self.actions_created = true;
-    let s = 1.0 / actions.len() as f32;
-    actions.into_iter().for_each(|action| {
+    self.add_actions(actions);
+  }
+
+  // adds the actions the node doesn't have yet, ranked after the existing
+  // ones. nodes reached by states with different legal actions gain them as
+  // they are met. the new actions get a uniform share of the priors, and the
+  // existing ones are scaled down for the priors to still sum to 1
+  pub(crate) fn add_actions(&mut self, actions: Vec<A>) {
+    let new: Vec<_> = actions
+      .into_iter()
+      .filter(|a| !self.actions.contains_key(a))
+      .collect();
+    let first = self.actions.len();
+    let s = 1.0 / (first + new.len()) as f32;
+    let existing: f32 = self.actions.values().map(|d| d.static_policy_score).sum();
+    if existing > 0.0 {
+      let scale = (1.0 - s * new.len() as f32) / existing;
+      for data in self.actions.values_mut() {
+        data.static_policy_score *= scale;
+      }
+    }
+    new.into_iter().enumerate().for_each(|(ix, action)| {
+      let rank = first + ix;
       self.actions.insert(
         action,
         ActionInfo {
           action_reward: RunningAverage::new(),
           value_of_next_state: RunningAverage::new(),
+          returns: RunningAverage::new(),
+          amaf: RunningAverage::new(),
           select_count: 0,
           static_policy_score: s,
+          virtual_visits: AtomicU32::new(0),
+          virtual_losses: AtomicU32::new(0),
+          available: AtomicU32::new(0),
+          proof: None,
+          value_bounds: None,
+          pruned: false,
+          rank: rank as u32,
+          outcomes: 0,
+          children: vec![],
+          chance: vec![],
+          cumulative: 0.0,
         },
       );
     });
@@ -144,6 +603,11 @@ impl ActionInfo {
     self.select_count
   }
 
+  // includes the virtual visits of trajectories in flight, for the bandits
+  pub(crate) fn virtual_select_count(&self) -> u32 {
+    self.select_count + self.virtual_visits.load(Ordering::Relaxed)
+  }
+
   pub(crate) fn increment_select_count(&mut self) {
     self.select_count += 1;
   }
@@ -151,6 +615,58 @@ impl ActionInfo {
   pub(crate) fn value(&self) -> f32 {
     self.action_reward.value() + self.value_of_next_state.value()
   }
+
+  pub(crate) fn logit(&self) -> f32 {
+    self.static_policy_score.max(1e-9).ln()
+  }
+
+  // variance of the returns, in the space normalised by `bounds`
+  pub(crate) fn normalised_variance(&self, bounds: &Bounds) -> f32 {
+    let range = bounds.high() - bounds.low();
+    if range > 0.0 {
+      self.returns.variance() / (range * range)
+    } else {
+      0.0
+    }
+  }
+
+  // value normalised by `bounds`, with every virtual loss counted as a sample
+  // at the lower bound
+  pub(crate) fn normalised_value(&self, bounds: &Bounds) -> f32 {
+    let v = bounds.normalise(self.value());
+    let losses = self.virtual_losses.load(Ordering::Relaxed);
+    if losses == 0 {
+      v
+    } else {
+      let n = self.value_of_next_state.count() as f32;
+      v * n / (n + losses as f32)
+    }
+  }
+
+  // only needs a shared reference, so that workers can apply it while
+  // selecting under the read lock
+  pub(crate) fn add_virtual(&self, visits: u32, losses: u32) {
+    self.virtual_visits.fetch_add(visits, Ordering::Relaxed);
+    self.virtual_losses.fetch_add(losses, Ordering::Relaxed);
+  }
+
+  pub(crate) fn mark_available(&self) {
+    self.available.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(crate) fn remove_virtual(&self, visits: u32, losses: u32) {
+    self.virtual_visits.fetch_sub(visits, Ordering::Relaxed);
+    self.virtual_losses.fetch_sub(losses, Ordering::Relaxed);
+  }
+
+  fn is_excluded(&self) -> bool {
+    self.pruned || matches!(&self.proof, Some(p) if p.outcome == Outcome::Loss)
+  }
+
+  fn clear_virtual(&mut self) {
+    *self.virtual_visits.get_mut() = 0;
+    *self.virtual_losses.get_mut() = 0;
+  }
 }
 
 impl<A: Display, O: Display> Display for Node<A, O> {
@@ -172,3 +688,157 @@ impl<A: Display, O: Display> Display for Node<A, O> {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use lib::utils::Bounds;
+
+  use super::{Forest, Node};
+
+  #[test]
+  fn test_advance_roots() {
+    let mut forest: Forest<u8, u8> = Forest::new(10);
+    let root = forest.new_root();
+    let a = forest.get_id_of_child(root, &0);
+    let b = forest.get_id_of_child(root, &1);
+    let a1 = forest.get_id_of_child(a, &0);
+    forest.get_id_of_child(b, &0);
+    forest.node_mut(a1).increment_select_count();
+    assert_eq!(forest.size(), 5);
+
+    forest.advance_roots(&[0]);
+    assert_eq!(forest.size(), 2);
+    let root = forest.roots()[0];
+    assert_eq!(forest.principal_variation(root), vec![0]);
+
+    // unseen observations get a fresh root
+    forest.advance_roots(&[1]);
+    assert_eq!(forest.size(), 1);
+  }
+
+  #[test]
+  fn test_principal_variation() {
+    let mut forest: Forest<u8, u8> = Forest::new(10);
+    let root = forest.new_root();
+    forest.node_mut(root).create_actions(vec![0, 1]);
+    // action 0 is the most selected, but the most selected child is reached
+    // by action 1
+    for (action, o, count) in [(0, 10, 2), (0, 11, 3), (1, 12, 4)] {
+      let child = forest.get_id_of_child(root, &o);
+      for _ in 0..count {
+        forest.node_mut(child).increment_select_count();
+        let data = forest.node_mut(root).actions.get_mut(&action).unwrap();
+        data.increment_select_count();
+        if !data.children.contains(&child) {
+          data.children.push(child);
+        }
+      }
+    }
+    assert_eq!(forest.principal_variation(root), vec![11]);
+  }
+
+  #[test]
+  fn test_compact() {
+    let mut forest: Forest<u8, u8> = Forest::new(10);
+    let root = forest.new_root();
+    let a = forest.get_id_of_child(root, &0);
+    let b = forest.get_id_of_child(root, &1);
+    forest.get_id_of_child(b, &0);
+    forest.node_mut(a).increment_select_count();
+    forest.node_mut(a).increment_select_count();
+    forest.node_mut(b).increment_select_count();
+
+    assert_eq!(forest.retained_size(2), 2);
+    let epoch = forest.epoch();
+    let remap = forest.compact(2);
+    assert_eq!(forest.size(), 2);
+    assert_eq!(forest.epoch(), epoch + 1);
+    assert!(remap[b.0].is_none());
+    let root = forest.roots()[0];
+    assert_eq!(forest.node(root).children.len(), 1);
+    assert_eq!(forest.principal_variation(root), vec![0]);
+  }
+
+  #[test]
+  fn test_transpositions() {
+    let mut forest: Forest<u8, u8> = Forest::new(10);
+    forest.enable_transpositions();
+    let root = forest.new_root();
+    let a = forest.get_id_of_transposition(root, &0, 0, 1, 10);
+    let b = forest.get_id_of_transposition(root, &1, 0, 1, 11);
+    let ab = forest.get_id_of_transposition(a, &1, 0, 2, 12);
+    let ba = forest.get_id_of_transposition(b, &0, 0, 2, 12);
+    assert_eq!(ab.0, ba.0);
+    // same key at another depth is a different node
+    let c = forest.get_id_of_transposition(ab, &2, 0, 3, 10);
+    assert_ne!(c.0, a.0);
+    assert_eq!(forest.size(), 5);
+
+    // after advancing, the shared node is found one level higher
+    forest.advance_roots(&[0]);
+    let root = forest.roots()[0];
+    let ab = forest.get_id_of_child(root, &1);
+    let other = forest.get_id_of_transposition(root, &3, 0, 1, 12);
+    assert_eq!(ab.0, other.0);
+  }
+
+  #[test]
+  fn test_value_bounds() {
+    let declared = vec![Bounds::new_known(0.0, 1.0); 2];
+    let mut node: Node<u8, u8> = Node::new();
+    node.create_actions(vec![0, 1, 2]);
+    node.actions.get_mut(&0).unwrap().value_bounds = Some(vec![Bounds::new_known(0.5, 0.5); 2]);
+    node.actions.get_mut(&1).unwrap().value_bounds = Some(vec![
+      Bounds::new_known(0.0, 0.4),
+      Bounds::new_known(0.6, 1.0),
+    ]);
+    node.update_value_bounds(0, &declared);
+
+    // 1 can't beat the draw of 0, 2 is still unknown
+    let candidates: Vec<_> = node.candidate_actions(None).map(|(a, _)| *a).collect();
+    assert_eq!(candidates, vec![0, 2]);
+    let bounds = node.value_bounds.as_ref().unwrap();
+    assert_eq!((bounds[0].low(), bounds[0].high()), (0.5, 1.0));
+    assert_eq!((bounds[1].low(), bounds[1].high()), (0.0, 1.0));
+  }
+
+  #[test]
+  fn test_virtual_loss() {
+    let mut forest: Forest<u8, u8> = Forest::new(10);
+    let root = forest.new_root();
+    forest.node_mut(root).create_actions(vec![0]);
+    let data = forest.node_mut(root).actions.get_mut(&0).unwrap();
+    data.increment_select_count();
+    data.value_of_next_state.add_sample(1.0, 1);
+    let bounds = Bounds::new_known(0.0, 1.0);
+
+    data.add_virtual(2, 1);
+    assert_eq!(data.virtual_select_count(), 3);
+    assert_eq!(data.select_count(), 1);
+    assert_eq!(data.normalised_value(&bounds), 0.5);
+    data.remove_virtual(2, 1);
+    assert_eq!(data.virtual_select_count(), 1);
+    assert_eq!(data.normalised_value(&bounds), 1.0);
+
+    data.add_virtual(1, 1);
+    forest.compact(0);
+    let data = &forest.node(forest.roots()[0]).actions[&0];
+    assert_eq!(data.virtual_select_count(), 1);
+  }
+
+  #[test]
+  fn test_add_actions() {
+    let mut forest: Forest<u8, u8> = Forest::new(10);
+    let root = forest.new_root();
+    let node = forest.node_mut(root);
+    node.create_actions(vec![0, 1]);
+    node.actions.get_mut(&0).unwrap().static_policy_score = 0.8;
+    node.actions.get_mut(&1).unwrap().static_policy_score = 0.2;
+    // a state with one more legal action, which gets a third of the priors
+    node.add_actions(vec![1, 2, 0]);
+    let prior = |a| node.actions[&a].static_policy_score;
+    assert!((prior(0) + prior(1) + prior(2) - 1.0).abs() < 1e-6);
+    assert!((prior(2) - 1.0 / 3.0).abs() < 1e-6);
+    assert!((prior(0) - 4.0 * prior(1)).abs() < 1e-6);
+  }
+}
@@ -0,0 +1,161 @@
+use super::{ActionInfo, Forest, Node, NodeId};
+use crate::solver::Proof;
+
+// read only view of a node, for inspecting the trees outside of the crate.
+// it borrows the forest, so the search has to be stopped or its lock held
+// while walking
+pub struct NodeCursor<'a, A, O> {
+  forest: &'a Forest<A, O>,
+  id: NodeId,
+}
+
+// statistics of an action of a node
+#[derive(Clone, Debug)]
+pub struct ActionStats<A> {
+  pub action: A,
+  pub select_count: u32,
+  // expected return, the immediate reward included
+  pub value: f32,
+  pub reward: f32,
+  pub prior: f32,
+  pub proof: Option<Proof>,
+}
+
+// a node on the most selected path of a tree
+#[derive(Clone, Debug)]
+pub struct PvStep<A, O> {
+  // observation that led to the node, none at the root and in open loop trees
+  pub observation: Option<O>,
+  pub select_count: u32,
+  pub value: f32,
+  // most selected action, in the nodes the agent of the tree acts in
+  pub action: Option<ActionStats<A>>,
+}
+
+impl<A, O> Clone for NodeCursor<'_, A, O> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<A, O> Copy for NodeCursor<'_, A, O> {}
+
+impl<A: Ord + Clone, O: Ord + Clone> Forest<A, O> {
+  pub fn cursor(&self, node_id: NodeId) -> NodeCursor<'_, A, O> {
+    NodeCursor {
+      forest: self,
+      id: node_id,
+    }
+  }
+}
+
+impl<'a, A: Ord + Clone, O: Ord + Clone> NodeCursor<'a, A, O> {
+  fn node(&self) -> &'a Node<A, O> {
+    self.forest.node(self.id)
+  }
+
+  fn at(&self, id: NodeId) -> Self {
+    self.forest.cursor(id)
+  }
+
+  pub fn id(&self) -> NodeId {
+    self.id
+  }
+
+  pub fn select_count(&self) -> u32 {
+    self.node().select_count()
+  }
+
+  pub fn value(&self) -> f32 {
+    self.node().value.value()
+  }
+
+  pub fn proof(&self) -> Option<&'a Proof> {
+    self.node().proof.as_ref()
+  }
+
+  // the actions of the node, empty unless the agent of the tree acts in it
+  pub fn actions(&self) -> Vec<ActionStats<A>> {
+    self
+      .node()
+      .actions
+      .iter()
+      .map(|(a, data)| ActionStats::new(a, data))
+      .collect()
+  }
+
+  pub fn action(&self, action: &A) -> Option<ActionStats<A>> {
+    let data = self.node().actions.get(action)?;
+    Some(ActionStats::new(action, data))
+  }
+
+  pub fn children(&self) -> impl Iterator<Item = (&'a O, Self)> + '_ {
+    self.node().children.iter().map(|(o, id)| (o, self.at(*id)))
+  }
+
+  pub fn child(&self, o: &O) -> Option<Self> {
+    self.node().children.get(o).map(|id| self.at(*id))
+  }
+
+  // child of an open loop tree, reached by the action
+  pub fn open_child(&self, action: &A) -> Option<Self> {
+    self.node().open_children.get(action).map(|id| self.at(*id))
+  }
+
+  // probability, immediate reward and child of every outcome of the action,
+  // recorded by chance node searches
+  pub fn chance_outcomes(&self, action: &A) -> Vec<(f32, f32, Self)> {
+    self.node().actions.get(action).map_or(vec![], |data| {
+      data
+        .chance
+        .iter()
+        .map(|e| (e.probability, e.reward, self.at(e.child)))
+        .collect()
+    })
+  }
+
+  // the path of most selected children, starting at this node. in the nodes
+  // the agent of the tree acts in, the next step is among the children
+  // reached by the most selected action
+  pub fn principal_variation(&self) -> Vec<PvStep<A, O>> {
+    let mut result = vec![];
+    let mut current = Some((None, *self));
+    while let Some((observation, cursor)) = current {
+      let node = cursor.node();
+      let most_selected = node
+        .actions
+        .iter()
+        .filter(|(_, data)| data.select_count() > 0)
+        .max_by_key(|(_, data)| data.select_count());
+      result.push(PvStep {
+        observation,
+        select_count: cursor.select_count(),
+        value: cursor.value(),
+        action: most_selected.map(|(a, data)| ActionStats::new(a, data)),
+      });
+      let reached = |id: &NodeId| most_selected.is_none_or(|(_, data)| data.children.contains(id));
+      let observed = node.children.iter().map(|(o, id)| (Some(o.clone()), *id));
+      let open = node.open_children.values().map(|id| (None, *id));
+      current = observed
+        .chain(open)
+        .filter(|(_, id)| reached(id))
+        .map(|(o, id)| (o, cursor.at(id)))
+        .filter(|(_, child)| child.select_count() > 0)
+        .max_by_key(|(_, child)| child.select_count());
+    }
+    result
+  }
+}
+
+impl<A: Clone> ActionStats<A> {
+  fn new(action: &A, data: &ActionInfo) -> Self {
+    Self {
+      action: action.clone(),
+      select_count: data.select_count(),
+      value: data.value(),
+      reward: data.action_reward.value(),
+      prior: data.static_policy_score,
+      proof: data.proof.clone(),
+    }
+  }
+}
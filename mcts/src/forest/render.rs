@@ -9,7 +9,7 @@ use graphviz_rust::{
 };
 
 use super::Forest;
-use crate::forest::Node;
+use crate::forest::{Node, NodeId};
 
 fn render<A: Ord + Display, O: Ord + Display>(
   forest: &Forest<A, O>,
@@ -33,10 +33,8 @@ fn render<A: Ord + Display, O: Ord + Display>(
   g.add_stmt(Stmt::Node(n));
 
   if !leaf {
-    let children = &node.children;
-
-    for (ix, o) in children.keys().enumerate() {
-      let child_id = render(forest, forest.node(children[o]), g, theta, depth - 1, count);
+    for (ix, (o, child)) in edges(node).into_iter().enumerate() {
+      let child_id = render(forest, forest.node(child), g, theta, depth - 1, count);
 
       let e = GEdge {
         ty: EdgeTy::Pair(
@@ -46,7 +44,7 @@ fn render<A: Ord + Display, O: Ord + Display>(
           )),
           Vertex::N(child_id),
         ),
-        attributes: vec![EdgeAttributes::label(format!("\"{}\"", o.to_string()))],
+        attributes: vec![EdgeAttributes::label(format!("\"{}\"", o))],
       };
       g.add_stmt(Stmt::Edge(e));
     }
@@ -82,14 +80,24 @@ pub fn save<A: Ord + Display, O: Ord + Display>(
   write!(f, "{}", g.print(&mut ctx)).unwrap();
 }
 
+// observations, or actions in open loop trees, leading to each child
+fn edges<A: Display, O: Display>(node: &Node<A, O>) -> Vec<(String, NodeId)> {
+  node
+    .children
+    .iter()
+    .map(|(o, c)| (o.to_string(), *c))
+    .chain(node.open_children.iter().map(|(a, c)| (a.to_string(), *c)))
+    .collect()
+}
+
 fn node_format<A: Ord + Display, O: Ord + Display>(node: &Node<A, O>, leaf: bool) -> String {
-  let children = &node.children;
+  let children = edges(node);
   let out_row = if leaf || children.is_empty() {
     "".to_string()
   } else {
     let mut result =
       "<table bgcolor=\"tomato\" border=\"0\" cellspacing=\"0\" cellborder=\"1\"><tr>".to_string();
-    for (ix, o) in children.keys().enumerate() {
+    for (ix, (o, _)) in children.iter().enumerate() {
       result.push_str(&format!("<td port=\"{ix}\">{o}</td>"));
     }
     result.push_str("</tr></table>");
@@ -1,12 +1,20 @@
-use forest::{Forest, Node};
+use std::time::{Duration, Instant};
+
 use lib::MctsProblem;
-use search::Trajectory;
+use rand::{distributions::WeightedIndex, prelude::Distribution};
+use search::StopHandle;
+use solver::Proof;
 
 pub mod bandits;
+pub mod concurrent;
 pub mod forest;
 pub mod rollout;
 pub mod search;
-mod experiments;
+pub mod solver;
+
+// scores, static policies and played (agent index, action) pairs of a block
+// of states, see `Expansion::block_expand_with_actions`
+pub type ExpansionsWithActions<A> = (Vec<Vec<f32>>, Vec<Vec<(A, f32)>>, Vec<Vec<(usize, A)>>);
 
 pub trait Expansion<P>: Copy
 where
@@ -28,28 +36,288 @@ where
     });
     (r1, r2)
   }
+
+  // same as `block_expand`, and also the (agent index, action) pairs played
+  // while evaluating every state, for AMAF statistics
+  fn block_expand_with_actions(
+    &self,
+    p: &P,
+    states: &[P::HiddenState],
+  ) -> ExpansionsWithActions<P::Action> {
+    let (v, pi) = self.block_expand(p, states);
+    (v, pi, vec![vec![]; states.len()])
+  }
 }
 
-#[derive(Clone, Copy)]
-pub struct SearchLimit {
-  node_count: Option<u32>,
+// common interface of `search::Search` and `concurrent::ConcurrentSearch`, so
+// that callers can switch between them
+pub trait SearchBackend<P: MctsProblem> {
+  type Worker;
+
+  fn create_workers(&self, count: usize) -> Vec<Self::Worker>;
+  fn start(&self, worker: &mut Self::Worker);
+  fn get_policy(&self) -> Vec<PolicyEntry<P::Action>>;
+  fn stop_handle(&self) -> StopHandle;
 }
 
-impl SearchLimit {
-  fn more(&self, n: u32) -> bool {
-    if self.node_count.map(|l| n > l).unwrap_or(false) {
-      return false;
+// statistics of one action at the root
+#[derive(Clone, Debug)]
+pub struct PolicyEntry<A> {
+  pub action: A,
+  // fraction of the root's selects
+  pub probability: f32,
+  pub select_count: u32,
+  pub value: f32,
+  // probability of the action in the policy improved by the completed values,
+  // a training target, see `search::Gumbel`
+  pub improved: f32,
+  // known result of the action, only set by searches with the solver enabled
+  pub proof: Option<Proof>,
+}
+
+// how the action to play is picked from the root statistics once the search
+// stops
+#[derive(Clone, Copy, Debug)]
+pub enum FinalSelection {
+  // highest value
+  MaxChild,
+  // most selected
+  RobustChild,
+  // the action that's both the most selected and the highest valued, with
+  // `SearchLimit::MaxRobust` searching until there is one. falls back to the
+  // most selected otherwise
+  MaxRobust,
+  // highest lower confidence bound, the value minus c / sqrt(select count)
+  SecureChild(f32),
+  // samples proportionally to select count^(1 / temperature), the temperature
+  // depending on the number of moves played. a temperature of 0 plays the
+  // most selected action
+  Temperature(TemperatureSchedule),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TemperatureSchedule {
+  Constant(f32),
+  // the temperature for the given number of moves, 0 afterwards
+  Step { temperature: f32, moves: u32 },
+  // initial * rate^moves, down to the minimum
+  Decay { initial: f32, rate: f32, min: f32 },
+}
+
+impl TemperatureSchedule {
+  pub fn temperature(&self, move_number: u32) -> f32 {
+    match *self {
+      TemperatureSchedule::Constant(t) => t,
+      TemperatureSchedule::Step { temperature, moves } => {
+        if move_number < moves {
+          temperature
+        } else {
+          0.0
+        }
+      }
+      TemperatureSchedule::Decay { initial, rate, min } => {
+        (initial * rate.powi(move_number as i32)).max(min)
+      }
     }
-    // more checks
+  }
+}
 
-    true
+impl FinalSelection {
+  // `move_number` counts the moves played before this one
+  pub fn select<A: Clone>(&self, policy: &[PolicyEntry<A>], move_number: u32) -> A {
+    let robust = || {
+      policy
+        .iter()
+        .max_by_key(|e| e.select_count)
+        .expect("the root has no actions")
+    };
+    let max = || {
+      policy
+        .iter()
+        .filter(|e| e.select_count > 0)
+        .max_by(|a, b| a.value.total_cmp(&b.value))
+        .unwrap_or_else(robust)
+    };
+    let entry = match *self {
+      FinalSelection::MaxChild => max(),
+      // when the two agree, the most selected is also the highest valued
+      FinalSelection::RobustChild | FinalSelection::MaxRobust => robust(),
+      FinalSelection::SecureChild(c) => policy
+        .iter()
+        .filter(|e| e.select_count > 0)
+        .max_by(|a, b| {
+          let bound = |e: &PolicyEntry<A>| e.value - c / (e.select_count as f32).sqrt();
+          bound(a).total_cmp(&bound(b))
+        })
+        .unwrap_or_else(robust),
+      FinalSelection::Temperature(schedule) => {
+        let t = schedule.temperature(move_number);
+        let most = robust();
+        if t <= f32::EPSILON || most.select_count == 0 {
+          most
+        } else {
+          // relative to the largest count, for the powers not to overflow
+          let weights = policy
+            .iter()
+            .map(|e| (e.select_count as f32 / most.select_count as f32).powf(1.0 / t));
+          let index = WeightedIndex::new(weights).unwrap();
+          &policy[index.sample(&mut lib::random::rng())]
+        }
+      }
+    };
+    entry.action.clone()
   }
+}
 
-  pub fn new(n: u32) -> Self {
-    SearchLimit {
-      node_count: Some(n),
+// stopping criteria for a search, checked once per block by every worker
+#[derive(Clone, Debug)]
+pub enum SearchLimit {
+  // select count of the root, not counting the selects a re-rooted search
+  // inherited
+  NodeCount(u32),
+  // wall clock budget, measured from the first call to `Search::start`
+  Duration(Duration),
+  Deadline(Instant),
+  // number of nodes allocated in the forest
+  ForestSize(usize),
+  // depth of the deepest trajectory seen so far
+  Depth(u32),
+  // same as NodeCount, but stops early once the most selected action at the
+  // root can no longer be overtaken with the remaining budget
+  Decisive(u32),
+  // select count of the first, continued up to the second until the most
+  // selected action at the root is also the highest valued, see
+  // `FinalSelection::MaxRobust`
+  MaxRobust(u32, u32),
+  // stops as soon as any of the limits is reached
+  Any(Vec<SearchLimit>),
+  // stops only when all of the limits are reached
+  All(Vec<SearchLimit>),
+}
+
+pub(crate) struct SearchProgress {
+  // selects of the root since it became the root
+  pub(crate) select_count: u32,
+  pub(crate) forest_size: usize,
+  pub(crate) max_depth: u32,
+  pub(crate) elapsed: Duration,
+  // select counts of the most and the second most selected actions at the root
+  pub(crate) top_counts: (u32, u32),
+  // whether the most selected action at the root has the highest value of the
+  // selected ones
+  pub(crate) max_robust: bool,
+}
+
+impl SearchLimit {
+  pub(crate) fn more(&self, progress: &SearchProgress) -> bool {
+    match self {
+      SearchLimit::NodeCount(n) => progress.select_count <= *n,
+      SearchLimit::Duration(d) => progress.elapsed < *d,
+      SearchLimit::Deadline(t) => Instant::now() < *t,
+      SearchLimit::ForestSize(n) => progress.forest_size < *n,
+      SearchLimit::Depth(d) => progress.max_depth < *d,
+      SearchLimit::Decisive(n) => {
+        if progress.select_count > *n {
+          return false;
+        }
+        let remaining = n - progress.select_count;
+        progress.top_counts.0 - progress.top_counts.1 <= remaining
+      }
+      SearchLimit::MaxRobust(n, cap) => {
+        progress.select_count <= *n || (!progress.max_robust && progress.select_count <= *cap)
+      }
+      SearchLimit::Any(limits) => limits.iter().all(|l| l.more(progress)),
+      SearchLimit::All(limits) => limits.iter().any(|l| l.more(progress)),
     }
   }
+
+  pub fn new(n: u32) -> Self {
+    SearchLimit::NodeCount(n)
+  }
+
+  // node count capped by a time budget, whichever runs out first
+  pub fn timed(n: u32, d: Duration) -> Self {
+    SearchLimit::Any(vec![SearchLimit::NodeCount(n), SearchLimit::Duration(d)])
+  }
 }
 
 pub use rollout::EmptyInit;
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use crate::{FinalSelection, PolicyEntry, SearchLimit, SearchProgress, TemperatureSchedule};
+
+  fn progress(select_count: u32, top_counts: (u32, u32)) -> SearchProgress {
+    SearchProgress {
+      select_count,
+      forest_size: 10,
+      max_depth: 3,
+      elapsed: Duration::from_millis(500),
+      top_counts,
+      max_robust: false,
+    }
+  }
+
+  #[test]
+  fn test_limits() {
+    let p = progress(100, (60, 30));
+    assert!(SearchLimit::new(100).more(&p));
+    assert!(!SearchLimit::new(99).more(&p));
+    assert!(!SearchLimit::Duration(Duration::from_millis(400)).more(&p));
+    assert!(!SearchLimit::ForestSize(10).more(&p));
+    assert!(!SearchLimit::Depth(3).more(&p));
+    // 20 more selects can't close a gap of 30
+    assert!(!SearchLimit::Decisive(120).more(&p));
+    assert!(SearchLimit::Decisive(130).more(&p));
+
+    let timed = SearchLimit::timed(1000, Duration::from_secs(1));
+    assert!(timed.more(&p));
+    assert!(!SearchLimit::timed(50, Duration::from_secs(1)).more(&p));
+    assert!(SearchLimit::All(vec![SearchLimit::new(50), SearchLimit::Depth(5)]).more(&p));
+    assert!(!SearchLimit::All(vec![SearchLimit::new(50), SearchLimit::Depth(2)]).more(&p));
+
+    // the most selected action isn't the highest valued yet
+    assert!(SearchLimit::MaxRobust(50, 200).more(&p));
+    assert!(!SearchLimit::MaxRobust(50, 99).more(&p));
+    let p = SearchProgress {
+      max_robust: true,
+      ..p
+    };
+    assert!(!SearchLimit::MaxRobust(50, 200).more(&p));
+  }
+
+  fn entry(action: u8, select_count: u32, value: f32) -> PolicyEntry<u8> {
+    PolicyEntry {
+      action,
+      probability: select_count as f32 / 100.0,
+      select_count,
+      value,
+      improved: 0.0,
+      proof: None,
+    }
+  }
+
+  #[test]
+  fn test_final_selection() {
+    let policy = vec![entry(0, 60, 0.5), entry(1, 36, 0.6), entry(2, 4, 0.9)];
+    assert_eq!(FinalSelection::MaxChild.select(&policy, 0), 2);
+    assert_eq!(FinalSelection::RobustChild.select(&policy, 0), 0);
+    // 0.6 - 1 / 6 beats 0.5 - 1 / 7.7 and 0.9 - 1 / 2
+    assert_eq!(FinalSelection::SecureChild(1.0).select(&policy, 0), 1);
+    let schedule = TemperatureSchedule::Step {
+      temperature: 1.0,
+      moves: 10,
+    };
+    assert_eq!(schedule.temperature(3), 1.0);
+    assert_eq!(FinalSelection::Temperature(schedule).select(&policy, 10), 0);
+    let decay = TemperatureSchedule::Decay {
+      initial: 1.0,
+      rate: 0.5,
+      min: 0.1,
+    };
+    assert_eq!(decay.temperature(1), 0.5);
+    assert_eq!(decay.temperature(10), 0.1);
+  }
+}
@@ -3,7 +3,7 @@ use std::fmt::Display;
 use lib::MctsProblem;
 use rand::seq::SliceRandom;
 
-use crate::{forest::Node, Expansion};
+use crate::{forest::Node, Expansion, ExpansionsWithActions};
 
 #[derive(Copy, Clone)]
 pub struct RandomRollout(pub u32);
@@ -21,22 +21,60 @@ where
     problem: &P,
     state: &<P as MctsProblem>::HiddenState,
   ) -> (Vec<f32>, Vec<(<P as MctsProblem>::Action, f32)>) {
+    (self.rollout(problem, state, None), vec![])
+  }
+
+  fn block_expand_with_actions(
+    &self,
+    problem: &P,
+    states: &[P::HiddenState],
+  ) -> ExpansionsWithActions<P::Action> {
+    let mut values = Vec::with_capacity(states.len());
+    let mut played = Vec::with_capacity(states.len());
+    for state in states {
+      let mut actions = vec![];
+      values.push(self.rollout(problem, state, Some(&mut actions)));
+      played.push(actions);
+    }
+    (values, vec![vec![]; states.len()], played)
+  }
+}
+
+impl RandomRollout {
+  // discounted return of every agent, the actions played are recorded in
+  // `played` along with the index of the agent that played them
+  fn rollout<P: MctsProblem>(
+    &self,
+    problem: &P,
+    state: &P::HiddenState,
+    mut played: Option<&mut Vec<(usize, P::Action)>>,
+  ) -> Vec<f32>
+  where
+    P::HiddenState: Clone,
+  {
     let mut _state = state.clone();
     let mut total = vec![0.0; problem.agents().len()];
-    let mut factor = 1.0;
+    let discounts = problem.discounts();
+    let mut factors = vec![1.0; total.len()];
     let mut horizon = self.0;
     while !problem.check_terminal(&_state) && horizon > 0 {
       let actions = problem.legal_actions(&_state);
-      let random_action = actions.choose(&mut rand::thread_rng()).unwrap();
+      let random_action = actions.choose(&mut lib::random::rng()).unwrap();
       //print!("{random_action} ");
+      if let Some(played) = played.as_mut() {
+        played.push((
+          problem.agent_to_act(&_state).into() as usize,
+          random_action.clone(),
+        ));
+      }
       let ro = problem.apply_action(&mut _state, random_action);
       for ix in 0..total.len() {
-        total[ix] += factor * ro[ix].0;
+        total[ix] += factors[ix] * ro[ix].0;
+        factors[ix] *= discounts[ix];
       }
-      factor *= problem.discount();
       horizon -= 1;
     }
-    (total, vec![])
+    total
   }
 }
 
@@ -1,14 +1,26 @@
 use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
   fmt::Debug,
-  sync::{Arc, RwLock},
+  sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    mpsc::Sender,
+    Arc, Mutex, MutexGuard, OnceLock, RwLock,
+  },
+  time::{Duration, Instant},
 };
 
-use lib::{utils::Bounds, MctsProblem};
+use lib::{
+  random,
+  utils::{sample_dirichlet, sample_gumbel, Bounds, RunningAverage},
+  MctsProblem,
+};
+use rand::{rngs::StdRng, SeedableRng};
 
 use crate::{
-  bandits::Bandit,
-  forest::{Forest, NodeId},
-  Expansion, SearchLimit,
+  bandits::{Bandit, Position, Selection},
+  forest::{cursor::PvStep, ChanceEdge, Forest, Node, NodeId},
+  solver::Proof,
+  Expansion, PolicyEntry, SearchBackend, SearchLimit, SearchProgress,
 };
 
 pub struct Search<P: MctsProblem, B, E> {
@@ -21,6 +33,248 @@ pub struct Search<P: MctsProblem, B, E> {
   bandit_policy: B,
   static_estimator: E,
   score_bounds: RwLock<Vec<Bounds>>,
+  // index of the agent to act at the root
+  root_agent: usize,
+  // select count the root inherited from the previous search in `advance`,
+  // the node count limits only count the selects after it
+  root_visits: u32,
+  started: OnceLock<Instant>,
+  max_depth: AtomicU32,
+  stop: StopHandle,
+  reporter: Option<Reporter<P::Action, P::Observation>>,
+  // the forest is compacted during the search when it grows past this
+  node_budget: Option<usize>,
+  virtual_loss: Option<VirtualLoss>,
+  transpositions: bool,
+  solver: bool,
+  // declared by the problem, seeds the normalisation bounds
+  value_bounds: Option<Vec<Bounds>>,
+  score_bounded: bool,
+  discounts: Vec<f32>,
+  // gathered only when the bandit reads them
+  amaf: bool,
+  action_widening: Option<Widening>,
+  observation_widening: Option<Widening>,
+  chance_nodes: bool,
+  open_loop: bool,
+  // at most this many of the states that reached each node are kept
+  particle_capacity: Option<usize>,
+  particles: Particles<P::HiddenState>,
+  information_sets: Option<InformationSets>,
+  root_noise: Option<RootNoise>,
+  // whether the noise was mixed into the priors of the current root
+  root_noised: AtomicBool,
+  gumbel: Option<Gumbel>,
+  // gumbel noise of the actions of the current root, and their visits when
+  // it was set
+  gumbel_noise: Mutex<BTreeMap<P::Action, (f32, u32)>>,
+  // number of actions considered, and the visits of the considered actions
+  // at every root visit, see `considered_visits`
+  halving: Mutex<(usize, Vec<u32>)>,
+  backup: Backup,
+  // seeds the generators of the workers, see `with_seed`
+  seed: Option<u64>,
+  // generator of the draws of the search outside of the workers
+  rng: Option<StdRng>,
+  workers_created: AtomicU64,
+}
+
+// information set MCTS for games of imperfect information. every trajectory
+// runs on a determinization sampled from the belief, and the nodes group the
+// states an observer can't tell apart. actions are only selected in the
+// determinizations they are legal in, and their exploration terms count the
+// visits in which they were legal. the agent to act at the root must have the
+// same legal actions in every determinization
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InformationSets {
+  // SO-ISMCTS, the nodes are the information sets of the agent to act at the
+  // root, and every agent decides from them. each agent still keeps its own
+  // statistics, in its own copy of the tree
+  SingleObserver,
+  // MO-ISMCTS, every agent decides in its own tree, built from its own
+  // observations
+  MultipleObserver,
+}
+
+// how the agents value the trajectories in games of more than two agents.
+// the values are backed up against the agent to act at the root, so the
+// statistics `advance` keeps were backed up against the previous one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backup {
+  // max-n, every agent backs up its own rewards
+  MaxN,
+  // every other agent backs up the negated rewards of the root agent, as if
+  // they played together against it
+  Paranoid,
+  // best reply search, the opponents back up as in paranoid search, but in
+  // every round of their moves between two moves of the root agent only one
+  // of them selects with the bandit. the others play the action with the
+  // highest prior. the replier is selected by ucb1 at the node of the first
+  // opponent move of the round, on the values the repliers got there
+  BestReply,
+}
+
+// progressive widening, a node or action visited n times allows
+// ceil(k * n^alpha) actions or outcomes, and at least one. the outcome limit
+// is soft: an outcome past it is resampled up to `MAX_RESAMPLES` times, and
+// kept as a new child when none of the resamples reaches an existing one, as
+// the observations have no distance to route it to the nearest child by. rare
+// outcomes of actions with many of them can go over the limit this way
+#[derive(Clone, Copy, Debug)]
+pub struct Widening {
+  pub k: f32,
+  pub alpha: f32,
+}
+
+// dirichlet noise mixed into the priors of the root agent's root, as in
+// alphazero, so that self play games explore actions the priors rule out.
+// the priors become (1 - epsilon) p + epsilon eta, eta ~ Dir(alpha)
+#[derive(Clone, Copy, Debug)]
+pub struct RootNoise {
+  pub alpha: f32,
+  pub epsilon: f32,
+}
+
+// gumbel muzero's root search. the `considered` actions with the highest
+// gumbel perturbed logits of the priors are searched by sequential halving
+// over `simulations`, comparing the perturbed logits plus the completed values
+// transformed by (c_visit + most visits) c_scale. unlike uct at the root, it
+// improves on the priors even with few simulations
+#[derive(Clone, Copy, Debug)]
+pub struct Gumbel {
+  pub considered: usize,
+  pub simulations: u32,
+  pub c_visit: f32,
+  pub c_scale: f32,
+}
+
+pub(crate) const C_VISIT: f32 = 50.0;
+pub(crate) const C_SCALE: f32 = 0.1;
+
+impl Gumbel {
+  pub fn new(considered: usize, simulations: u32) -> Self {
+    Self {
+      considered,
+      simulations,
+      c_visit: C_VISIT,
+      c_scale: C_SCALE,
+    }
+  }
+}
+
+// the visits the action to select at the root has in each simulation of
+// sequential halving, when `considered` actions of the root are searched
+// with a budget of `simulations`. the halving phases get equal shares of the
+// budget, and the sequence continues with the last two actions past it
+fn considered_visits(considered: usize, simulations: u32, len: usize) -> Vec<u32> {
+  if considered <= 1 {
+    return (0..len as u32).collect();
+  }
+  let log2 = (considered as f32).log2().ceil() as u32;
+  let mut sequence = Vec::with_capacity(len);
+  let mut visits = vec![0; considered];
+  let mut remaining = considered;
+  while sequence.len() < len {
+    let extra = (simulations / (log2 * remaining as u32)).max(1);
+    for _ in 0..extra {
+      sequence.extend_from_slice(&visits[..remaining]);
+      visits[..remaining].iter_mut().for_each(|v| *v += 1);
+    }
+    remaining = (remaining / 2).max(2);
+  }
+  sequence.truncate(len);
+  sequence
+}
+
+// attempts to resample an outcome that leads to an existing child, before
+// giving up and going past the widening limit
+const MAX_RESAMPLES: usize = 32;
+
+// discourages trajectories in flight, from this and other workers, from
+// selecting the same actions. applied when an action is selected and reverted
+// when the trajectory is backpropagated
+#[derive(Clone, Copy, Debug)]
+pub enum VirtualLoss {
+  // counts every trajectory in flight as this many visits that lost
+  Loss(u32),
+  // counts every trajectory in flight as this many visits, without changing
+  // the value of the action
+  Visits(u32),
+}
+
+// cooperative cancellation of a search, every worker polls it once per block
+#[derive(Clone, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+// snapshot of a running search, passed to progress callbacks
+#[derive(Clone, Debug)]
+pub struct SearchInfo<A, O> {
+  // selects since the root was set, or the budget restarted
+  pub select_count: u32,
+  pub elapsed: Duration,
+  pub nodes_per_sec: f32,
+  // select count and value of every action at the root
+  pub root_actions: Vec<(A, u32, f32)>,
+  pub best_action: Option<A>,
+  // observations along the most selected path in the root agent's tree
+  pub pv: Vec<O>,
+}
+
+type ProgressCallback<A, O> = Box<dyn Fn(SearchInfo<A, O>) + Send + Sync>;
+
+struct Reporter<A, O> {
+  interval: Duration,
+  last: Mutex<Option<Instant>>,
+  callback: ProgressCallback<A, O>,
+}
+
+// the particles of every node, sharded by node id so that the workers adding
+// to different nodes don't wait on one lock
+struct Particles<S> {
+  shards: Vec<Mutex<HashMap<NodeId, Vec<S>>>>,
+}
+
+const PARTICLE_SHARDS: usize = 64;
+
+impl<S: Clone> Particles<S> {
+  fn new() -> Self {
+    Self {
+      shards: (0..PARTICLE_SHARDS)
+        .map(|_| Mutex::new(HashMap::new()))
+        .collect(),
+    }
+  }
+
+  fn shard(&self, id: NodeId) -> MutexGuard<'_, HashMap<NodeId, Vec<S>>> {
+    self.shards[id.0 % PARTICLE_SHARDS].lock().unwrap()
+  }
+
+  fn get(&self, id: NodeId) -> Option<Vec<S>> {
+    self.shard(id).get(&id).cloned()
+  }
+
+  // adds the state to the particles of the node, unless it has `capacity`
+  fn push(&self, id: NodeId, state: &S, capacity: usize) {
+    let mut shard = self.shard(id);
+    let states = shard.entry(id).or_default();
+    if states.len() < capacity {
+      states.push(state.clone());
+    }
+  }
+
+  // moves the particles of the nodes a compaction kept to their new ids, and
+  // drops the others. every shard is locked, in order, while moving
+  fn remap(&self, remap: &[Option<NodeId>]) {
+    let mut shards: Vec<_> = self.shards.iter().map(|s| s.lock().unwrap()).collect();
+    let kept: Vec<_> = shards
+      .iter_mut()
+      .flat_map(|shard| shard.drain().collect::<Vec<_>>())
+      .filter_map(|(id, states)| remap[id.0].map(|id| (id, states)))
+      .collect();
+    for (id, states) in kept {
+      shards[id.0 % PARTICLE_SHARDS].insert(id, states);
+    }
+  }
 }
 
 #[derive(Clone)]
@@ -33,9 +287,15 @@ pub struct Worker<S, A: Clone> {
 
   // expansion backprops by itself.
   // trajectories that are terminated during select phase are
-  // queued here. these have terminal value zero, as they end
-  // in terminal nodes
-  trajectories_awaiting_backprop: Vec<Trajectory<A>>,
+  // queued here along with their leaf values. these are zero for
+  // terminal nodes, and the proven values for nodes solved by the solver
+  trajectories_awaiting_backprop: Vec<(Trajectory<A>, Vec<f32>)>,
+
+  // epoch of the forest the trajectories point into
+  epoch: u32,
+
+  // installed as the generator of the thread while the worker runs
+  rng: Option<StdRng>,
 }
 
 #[derive(Clone)]
@@ -45,6 +305,13 @@ pub struct Trajectory<A: Clone> {
 
   // nodeId, emitted reward and the selected action (along with the index of agent)
   branch: Vec<(Vec<(NodeId, f32)>, (usize, A))>,
+
+  // how each action of the branch was selected
+  selections: Vec<Selection<A>>,
+
+  // the depth of the first opponent move of every round and the opponent
+  // that replies in it, in best reply search
+  repliers: Vec<(usize, usize)>,
 }
 
 impl<P: MctsProblem, B, E> Search<P, B, E>
@@ -67,6 +334,14 @@ where
     for _ in 0..agent_count {
       forest.new_root();
     }
+    let root_agent = problem
+      .agent_to_act(&problem.sample_h_state(&b_state))
+      .into() as usize;
+    let value_bounds = problem.value_bounds();
+    let discounts = problem.discounts();
+    let score_bounds = value_bounds
+      .clone()
+      .unwrap_or_else(|| vec![Bounds::new(); agent_count]);
 
     Search {
       problem,
@@ -75,15 +350,293 @@ where
       block_size,
       limit,
       bandit_policy,
-      score_bounds: RwLock::new(vec![Bounds::new(); agent_count]),
+      score_bounds: RwLock::new(score_bounds),
       static_estimator: node_init,
+      root_agent,
+      root_visits: 0,
+      started: OnceLock::new(),
+      max_depth: AtomicU32::new(0),
+      stop: StopHandle::new(),
+      reporter: None,
+      node_budget: None,
+      virtual_loss: None,
+      transpositions: false,
+      solver: false,
+      value_bounds,
+      score_bounded: false,
+      discounts,
+      amaf: bandit_policy.needs_amaf(),
+      action_widening: None,
+      observation_widening: None,
+      chance_nodes: false,
+      open_loop: false,
+      particle_capacity: None,
+      particles: Particles::new(),
+      information_sets: None,
+      root_noise: None,
+      root_noised: AtomicBool::new(false),
+      gumbel: None,
+      gumbel_noise: Mutex::new(BTreeMap::new()),
+      halving: Mutex::new((0, vec![])),
+      backup: Backup::MaxN,
+      seed: None,
+      rng: None,
+      workers_created: AtomicU64::new(0),
     }
   }
 
+  // only the highest ranked actions by the static policy of the expansion can
+  // be selected, more are allowed as the node is visited
+  pub fn with_action_widening(mut self, widening: Widening) -> Self {
+    self.action_widening = Some(widening);
+    self
+  }
+
+  // limits the number of children reached through each action, outcomes that
+  // would create one more are resampled from the state before the action
+  // until they lead to an existing child
+  pub fn with_observation_widening(mut self, widening: Widening) -> Self {
+    self.observation_widening = Some(widening);
+    self
+  }
+
+  // keys the children of every node by the action taken instead of the
+  // observations received, the states are resimulated from the root by each
+  // trajectory. the legal actions of a node must not depend on the sampled
+  // state. observations are ignored, so this can't be combined with
+  // transpositions, observation widening or chance nodes
+  pub fn with_open_loop(mut self) -> Self {
+    self.open_loop = true;
+    self
+  }
+
+  // the noise is drawn once per root, when its actions are first created or
+  // the search is started after `advance`
+  pub fn with_root_noise(mut self, noise: RootNoise) -> Self {
+    self.root_noise = Some(noise);
+    self
+  }
+
+  // selects at the root agent's root by gumbel sampling and sequential
+  // halving, the bandit still selects everywhere else
+  pub fn with_gumbel(mut self, gumbel: Gumbel) -> Self {
+    self.gumbel = Some(gumbel);
+    self
+  }
+
+  // gives every worker its own generator, the n-th worker created by the
+  // search being seeded with seed + n, and the draws of `advance` with
+  // seed - 1. a search of one worker, on a problem that draws from
+  // `lib::random::rng`, is then reproducible
+  pub fn with_seed(mut self, seed: u64) -> Self {
+    self.seed = Some(seed);
+    self.rng = Some(StdRng::seed_from_u64(seed.wrapping_sub(1)));
+    self
+  }
+
+  pub fn with_backup(mut self, backup: Backup) -> Self {
+    self.backup = backup;
+    self
+  }
+
+  pub fn with_information_sets(mut self, information_sets: InformationSets) -> Self {
+    self.information_sets = Some(information_sets);
+    self
+  }
+
+  // keeps up to `capacity` of the states sampled at every node, as in POMCP.
+  // the particles of the root's children make the belief after the next
+  // step, see `particles_after`
+  pub fn with_particles(mut self, capacity: usize) -> Self {
+    self.particle_capacity = Some(capacity);
+    self
+  }
+
+  // turns every action of a problem with `MctsProblem::chance_outcomes` into
+  // an expectation node. its value is the probability weighted average over
+  // the children reached so far, instead of the average of the samples that
+  // went through it
+  pub fn with_chance_nodes(mut self) -> Self {
+    self.chance_nodes = true;
+    self
+  }
+
+  // keeps pessimistic and optimistic bounds of every agent's return in each
+  // node, starting from `MctsProblem::value_bounds`. actions that can't do
+  // better for the agent to act than another one are never selected, and
+  // nodes with exact bounds are treated as terminal
+  pub fn with_score_bounds(mut self) -> Self {
+    assert!(
+      self.value_bounds.is_some(),
+      "score bounded search needs the problem to declare value bounds"
+    );
+    self.score_bounded = true;
+    self
+  }
+
+  // proves wins, losses and draws of two player games, see `solver`. proven
+  // nodes are treated as terminal, proven losses are never selected unless
+  // every action is lost, and the search stops once the root is proven. the
+  // proofs are only sound in deterministic zero sum games: searches with
+  // chance nodes or open loop trees are rejected, and so is any action that
+  // reaches a second outcome
+  pub fn with_solver(mut self) -> Self {
+    assert_eq!(
+      self.problem.agents().len(),
+      2,
+      "the solver only proves results of two player games"
+    );
+    self.solver = true;
+    self
+  }
+
+  // shares the nodes of states with the same `MctsProblem::transposition_key`.
+  // actions keep their own select counts, but their values are read from the
+  // shared child nodes during backpropagation
+  pub fn with_transpositions(mut self) -> Self {
+    self.forest.get_mut().unwrap().enable_transpositions();
+    self.transpositions = true;
+    self
+  }
+
+  pub fn with_virtual_loss(mut self, virtual_loss: VirtualLoss) -> Self {
+    self.virtual_loss = Some(virtual_loss);
+    self
+  }
+
+  // compacts the forest whenever it grows past `budget` nodes, dropping the
+  // least selected subtrees. in flight trajectories of every worker are
+  // discarded when that happens
+  pub fn with_node_budget(mut self, budget: usize) -> Self {
+    self.node_budget = Some(budget);
+    self
+  }
+
+  // calls `callback` from one of the workers at most once every `interval`
+  pub fn with_progress(
+    mut self,
+    interval: Duration,
+    callback: impl Fn(SearchInfo<P::Action, P::Observation>) + Send + Sync + 'static,
+  ) -> Self {
+    self.reporter = Some(Reporter {
+      interval,
+      last: Mutex::new(None),
+      callback: Box::new(callback),
+    });
+    self
+  }
+
+  pub fn with_progress_sender(
+    self,
+    interval: Duration,
+    sender: Sender<SearchInfo<P::Action, P::Observation>>,
+  ) -> Self
+  where
+    P::Action: Send + 'static,
+    P::Observation: Send + 'static,
+  {
+    self.with_progress(interval, move |info| {
+      // the receiver going away is not a reason to stop searching
+      let _ = sender.send(info);
+    })
+  }
+
+  // re-roots every agent's tree at the child reached by `observations`, or by
+  // `action` in open loop searches, keeping the statistics of the subtrees
+  // that are still reachable. existing workers restart their trajectories
+  // from the new roots
+  pub fn advance(
+    &mut self,
+    b_state: Arc<P::BeliefState>,
+    action: &P::Action,
+    observations: &[P::Observation],
+  ) {
+    let forest = self.forest.get_mut().unwrap();
+    let root = forest.node(forest.roots()[self.root_agent]);
+    assert!(
+      !root.actions_created() || root.actions.contains_key(action),
+      "the action isn't legal at the root"
+    );
+    let remap = if self.open_loop {
+      forest.advance_roots_by_action(action)
+    } else if self.information_sets == Some(InformationSets::SingleObserver) {
+      let observations = vec![observations[self.root_agent].clone(); observations.len()];
+      forest.advance_roots(&observations)
+    } else {
+      forest.advance_roots(observations)
+    };
+    self.particles.remap(&remap);
+    let h_state = Self::seeded(&mut self.rng, || self.problem.sample_h_state(&b_state));
+    self.root_agent = self.problem.agent_to_act(&h_state).into() as usize;
+    self.b_state = b_state;
+    self.restart_budget();
+    self.root_noised = AtomicBool::new(false);
+    self.gumbel_noise.get_mut().unwrap().clear();
+    *self.halving.get_mut().unwrap() = (0, vec![]);
+  }
+
+  // gives the next `start` the whole limit again, at the same root. the time,
+  // the selects and the depth counted so far are dropped, the tree is kept
+  pub fn restart_budget(&mut self) {
+    let forest = self.forest.get_mut().unwrap();
+    self.root_visits = forest.node(forest.roots()[0]).select_count();
+    self.started = OnceLock::new();
+    self.max_depth = AtomicU32::new(0);
+  }
+
+  // drops every subtree whose root was selected less than `min_select_count`
+  // times, can be called while workers are running
+  pub fn collect_garbage(&self, min_select_count: u32) {
+    let remap = self.forest.write().unwrap().compact(min_select_count);
+    self.particles.remap(&remap);
+  }
+
+  // states sampled at the child of the root agent's root reached by `action`
+  // and `observations`, they approximate the belief after that step
+  pub fn particles_after(
+    &self,
+    action: &P::Action,
+    observations: &[P::Observation],
+  ) -> Vec<P::HiddenState> {
+    let forest = self.forest.read().unwrap();
+    let root = forest.roots()[self.root_agent];
+    forest
+      .find_child(root, &observations[self.root_agent], action)
+      .and_then(|child| self.particles.get(child))
+      .unwrap_or_default()
+  }
+
+  pub fn stop_handle(&self) -> StopHandle {
+    self.stop.clone()
+  }
+
   pub fn start(&self, worker: &mut Worker<P::HiddenState, P::Action>) {
+    let mut rng = worker.rng.take();
+    Self::seeded(&mut rng, || self.run(worker));
+    worker.rng = rng;
+  }
+
+  fn run(&self, worker: &mut Worker<P::HiddenState, P::Action>) {
+    debug_assert!(
+      !self.open_loop
+        || !(self.transpositions || self.chance_nodes || self.observation_widening.is_some()),
+      "open loop search ignores observations"
+    );
+    assert!(
+      self.backup == Backup::MaxN || !(self.solver || self.score_bounded),
+      "proofs and bounds are of the agents' own rewards"
+    );
+    assert!(
+      !self.solver || !(self.chance_nodes || self.open_loop),
+      "the solver only proves results of deterministic games"
+    );
+    let started = *self.started.get_or_init(Instant::now);
     // initialize root node if needed
     {
       let mut guard = self.forest.write().unwrap();
+      if worker.epoch != guard.epoch() {
+        self.reset_worker(&guard, worker);
+      }
       for (state, trajectory) in worker
         .states_in_flight
         .iter()
@@ -92,48 +645,68 @@ where
         let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
         let node_id = trajectory.current_[current_agent_ix];
         let node = guard.node_mut(node_id);
-        if !node.actions_created() {
-          node.create_actions(self.problem.legal_actions(state));
+        if !node.actions_created() || self.has_missing_actions(node, state) {
+          self.create_actions(node, state);
           let (_, p) = self.static_estimator.expand(&self.problem, state);
           for (a, pa) in p {
             node.actions.get_mut(&a).unwrap().static_policy_score = pa;
           }
+          if self.action_widening.is_some() {
+            node.rank_actions();
+          }
         }
       }
+      self.add_root_noise(&mut guard);
     }
 
     loop {
       //println!("worker trajectories: {:?}", worker.trajectories_in_flight);
       // select actions
       let agents_and_actions: Vec<_> = {
-        //let guard = self.forest.read().unwrap();
-        let bounds_guard = self.score_bounds.read().unwrap();
+        // the forest is always locked before the bounds, same as in the
+        // descend phase below
+        let guard = self.forest.read().unwrap();
+        if worker.epoch != guard.epoch() {
+          self.reset_worker(&guard, worker);
+        }
         // check if search budget remains
-        let select_count_root = {
-          let guard = self.forest.read().unwrap();
-          guard.node(guard.roots()[0]).select_count()
-        };
-        if !self.limit.more(select_count_root) {
+        self.report(&guard, started);
+        let progress = self.progress(&guard, started);
+        let solved = self
+          .known_values(guard.node(guard.roots()[self.root_agent]))
+          .is_some();
+        if self.stop.is_stopped() || solved || !self.limit.more(&progress) {
+          if self.virtual_loss.is_some() {
+            self.revert_worker(&guard, worker);
+          }
           return;
         }
+        let bounds_guard = self.score_bounds.read().unwrap();
         worker
           .trajectories_in_flight
           .iter_mut()
           .zip(worker.states_in_flight.iter_mut())
           .map(|(trajectory, state)| {
-            let guard = self.forest.read().unwrap();
             if self.problem.check_terminal(&state) {
               worker
                 .trajectories_awaiting_backprop
-                .push(trajectory.clone());
+                .push((trajectory.clone(), vec![0.0; trajectory.current_.len()]));
+              *state = self.problem.sample_h_state(&self.b_state);
+              self.restart_trajectory(&guard, trajectory);
+            }
+            let node =
+              guard.node(trajectory.current_[self.problem.agent_to_act(state).into() as usize]);
+            if let Some(values) = self.known_values(node) {
+              worker
+                .trajectories_awaiting_backprop
+                .push((trajectory.clone(), values));
               *state = self.problem.sample_h_state(&self.b_state);
               self.restart_trajectory(&guard, trajectory);
             }
             // its guaranteed that the state is not terminal
-            if !guard
-              .node(trajectory.current_[self.problem.agent_to_act(state).into() as usize])
-              .actions_created()
-            {
+            let node =
+              guard.node(trajectory.current_[self.problem.agent_to_act(state).into() as usize]);
+            if !node.actions_created() || self.has_missing_actions(node, state) {
               worker.states_awaiting_expansion.push(state.clone());
               worker
                 .trajectories_awaiting_expansion
@@ -145,31 +718,86 @@ where
 
             // need this before applying action to states that can change the aganet to act
             let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+            let node = guard.node(trajectory.current_[current_agent_ix]);
+            let available = self.information_sets.map(|_| {
+              let available: BTreeSet<_> = self.problem.legal_actions(state).into_iter().collect();
+              available
+                .iter()
+                .for_each(|a| node.actions[a].mark_available());
+              available
+            });
             // its guaranteed that the trajectory is not terminal
-            (
+            let position = Position {
+              agent: current_agent_ix,
+              depth: trajectory.branch.len() as u32,
+            };
+            let at_root = trajectory.branch.is_empty() && current_agent_ix == self.root_agent;
+            let mut selection = Selection {
+              probability: None,
+              available: None,
+            };
+            // picks the round's replier in best reply search, before matching
+            let replies = self.replies(
+              trajectory,
               current_agent_ix,
-              self.bandit_policy.select(
-                state,
-                guard.node(trajectory.current_[current_agent_ix]),
-                &bounds_guard[current_agent_ix],
-              ),
-            )
+              node,
+              &bounds_guard[current_agent_ix],
+            );
+            let action = match (self.gumbel, replies) {
+              (Some(gumbel), _) if at_root => self.gumbel_select(&gumbel, node, available.as_ref()),
+              (_, false) => Self::ordered_action(node, available.as_ref()),
+              (_, true) => {
+                let action = self.bandit_policy.select(
+                  state,
+                  node,
+                  available.as_ref(),
+                  &bounds_guard[current_agent_ix],
+                  position,
+                );
+                selection.probability =
+                  self
+                    .bandit_policy
+                    .probability(node, &action, available.as_ref(), position);
+                if selection.probability.is_some() {
+                  selection.available = available;
+                }
+                action
+              }
+            };
+            if let Some(virtual_loss) = self.virtual_loss {
+              let (visits, losses) = virtual_loss.amounts();
+              node.actions[&action].add_virtual(visits, losses);
+            }
+            (current_agent_ix, action, selection)
           })
           .collect()
       };
-      let (agents, actions): (Vec<_>, Vec<_>) = agents_and_actions.into_iter().unzip();
+      let mut selections = Vec::with_capacity(agents_and_actions.len());
+      let (agents, actions): (Vec<_>, Vec<_>) = agents_and_actions
+        .into_iter()
+        .map(|(agent, action, selection)| {
+          selections.push(selection);
+          (agent, action)
+        })
+        .unzip();
 
       // apply_actions
+      let pre_states = (self.observation_widening.is_some() || self.chance_nodes)
+        .then(|| worker.states_in_flight.clone());
       let outcomes = self
         .problem
         .apply_action_batched(&mut worker.states_in_flight, &actions);
 
       let expansion_result = if worker.states_awaiting_expansion.len() >= self.block_size as usize {
-        Some(
+        let states = &worker.states_awaiting_expansion;
+        Some(if self.amaf {
           self
             .static_estimator
-            .block_expand(&self.problem, &worker.states_awaiting_expansion),
-        )
+            .block_expand_with_actions(&self.problem, states)
+        } else {
+          let (v, p) = self.static_estimator.block_expand(&self.problem, states);
+          (v, p, vec![vec![]; states.len()])
+        })
       } else {
         None
       };
@@ -179,14 +807,14 @@ where
       // descend tree
       {
         let mut guard = self.forest.write().unwrap();
+        if worker.epoch != guard.epoch() {
+          // the forest was compacted after the actions were selected
+          self.reset_worker(&guard, worker);
+          continue;
+        }
         let mut bound_guard = self.score_bounds.write().unwrap();
-        for trajectory in worker.trajectories_awaiting_backprop.iter() {
-          self.backpropogate(
-            &mut guard,
-            &mut bound_guard,
-            trajectory,
-            vec![0.0; trajectory.current_.len()],
-          );
+        for (trajectory, values) in worker.trajectories_awaiting_backprop.drain(..) {
+          self.backpropogate(&mut guard, &mut bound_guard, &trajectory, values, true, &[]);
         }
         worker.trajectories_awaiting_backprop.clear();
 
@@ -197,32 +825,35 @@ where
           .zip(worker.trajectories_awaiting_expansion.iter_mut())
         {
           let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
-          if !guard
-            .node(trajectory.current_[current_agent_ix])
-            .actions_created()
-          {
-            let node = guard.node_mut(trajectory.current_[current_agent_ix]);
-            node.create_actions(self.problem.legal_actions(state));
+          let node = guard.node_mut(trajectory.current_[current_agent_ix]);
+          if !node.actions_created() || self.has_missing_actions(node, state) {
+            self.create_actions(node, state);
           }
         }
 
         if expansion_result.is_some() {
-          let (v, p) = expansion_result.unwrap();
-          for ((state, trajectory), (value, static_policy)) in worker
+          let (v, p, played) = expansion_result.unwrap();
+          for ((state, trajectory), ((value, static_policy), played)) in worker
             .states_awaiting_expansion
             .iter()
             .zip(worker.trajectories_awaiting_expansion.iter())
-            .zip(v.into_iter().zip(p.into_iter()))
+            .zip(v.into_iter().zip(p.into_iter()).zip(played.iter()))
           {
-            self.backpropogate(&mut guard, &mut bound_guard, trajectory, value);
+            self.backpropogate(
+              &mut guard,
+              &mut bound_guard,
+              trajectory,
+              value,
+              false,
+              played,
+            );
             let current_agent_ix = self.problem.agent_to_act(state).into() as usize;
+            let node = guard.node_mut(trajectory.current_[current_agent_ix]);
             for (a, pa) in static_policy {
-              guard
-                .node_mut(trajectory.current_[current_agent_ix])
-                .actions
-                .get_mut(&a)
-                .unwrap()
-                .static_policy_score = pa;
+              node.actions.get_mut(&a).unwrap().static_policy_score = pa;
+            }
+            if self.action_widening.is_some() {
+              node.rank_actions();
             }
           }
           worker.trajectories_awaiting_expansion.clear();
@@ -232,117 +863,767 @@ where
         worker
           .trajectories_in_flight
           .iter_mut()
-          .zip(worker.states_in_flight.iter())
+          .zip(worker.states_in_flight.iter_mut())
           .zip(
             actions
               .into_iter()
               .zip(agents.into_iter())
-              .zip(outcomes.into_iter()),
+              .zip(outcomes.into_iter())
+              .zip(selections),
           )
+          .enumerate()
           .for_each(
-            |((trajectory, state), ((action, agent_ix), outcomes_and_rewards))| {
+            |(
+              i,
+              ((trajectory, state), (((action, agent_ix), mut outcomes_and_rewards), selection)),
+            )| {
               // increment select_counts
               // descend nodes
 
               // the state here has the action applied to it, but the trajectory's current points to the old one
 
+              if let (Some(widening), Some(pre_states)) = (self.observation_widening, &pre_states) {
+                self.widen_outcome(
+                  &guard,
+                  trajectory.current_[agent_ix],
+                  (self.observer(agent_ix), &action),
+                  widening,
+                  (&pre_states[i], state),
+                  &mut outcomes_and_rewards,
+                );
+              }
+
               let mut children_ix = Vec::with_capacity(trajectory.current_.len());
               let mut branch_entry = Vec::with_capacity(trajectory.current_.len());
+              let key = if self.transpositions {
+                self.problem.transposition_key(state)
+              } else {
+                None
+              };
+              let depth = trajectory.branch.len() as u32 + 1;
               for (ix, node_id) in trajectory.current_.iter().enumerate() {
+                let o = &outcomes_and_rewards[self.observer(ix)].1;
                 {
                   let node = guard.node_mut(*node_id);
                   node.increment_select_count();
                   if ix == agent_ix {
-                    node
-                      .actions
-                      .get_mut(&action)
-                      .unwrap()
-                      .increment_select_count();
+                    let new_outcome = !self.open_loop && !node.has_child(o);
+                    let data = node.actions.get_mut(&action).unwrap();
+                    data.increment_select_count();
+                    if new_outcome {
+                      data.outcomes += 1;
+                    }
+                    if let Some(widening) = self.action_widening {
+                      node.widened = widening.limit(node.select_count());
+                    }
+                  }
+                }
+                let child = match key {
+                  _ if self.open_loop => guard.get_id_of_open_child(*node_id, &action),
+                  Some(key) => guard.get_id_of_transposition(*node_id, o, ix, depth, key),
+                  None => guard.get_id_of_child(*node_id, o),
+                };
+                if ix == agent_ix {
+                  let data = guard.node_mut(*node_id).actions.get_mut(&action).unwrap();
+                  if !data.children.contains(&child) {
+                    data.children.push(child);
                   }
+                  // a proof from one outcome of a stochastic action is unsound
+                  assert!(
+                    !self.solver || data.children.len() == 1,
+                    "the solver only proves results of deterministic games"
+                  );
                 }
-                children_ix.push(guard.get_id_of_child(*node_id, &outcomes_and_rewards[ix].1));
+                children_ix.push(child);
                 branch_entry.push((*node_id, outcomes_and_rewards[ix].0));
               }
+              if let (true, Some(pre_states)) = (self.chance_nodes, &pre_states) {
+                let child = children_ix[agent_ix];
+                let observer = self.observer(agent_ix);
+                let o = &outcomes_and_rewards[observer].1;
+                let data = guard
+                  .node_mut(trajectory.current_[agent_ix])
+                  .actions
+                  .get_mut(&action)
+                  .unwrap();
+                if !data.chance.iter().any(|e| e.child == child) {
+                  if let Some((probability, reward)) =
+                    self.chance_of(&pre_states[i], &action, observer, o)
+                  {
+                    data.chance.push(ChanceEdge {
+                      child,
+                      probability,
+                      reward,
+                    });
+                  }
+                }
+              }
+              if let Some(capacity) = self.particle_capacity {
+                for child in children_ix.iter() {
+                  self.particles.push(*child, state, capacity);
+                }
+              }
               trajectory.current_ = children_ix;
               trajectory.branch.push((branch_entry, (agent_ix, action)));
+              trajectory.selections.push(selection);
+              self
+                .max_depth
+                .fetch_max(trajectory.branch.len() as u32, Ordering::Relaxed);
             },
           );
+
+        if let Some(budget) = self.node_budget {
+          if guard.size() > budget {
+            self.compact_to(&mut guard, budget * 3 / 4);
+          }
+        }
       }
     }
   }
 
+  // compacts with the smallest select count threshold that brings the forest
+  // down to `target` nodes
+  fn compact_to(&self, forest: &mut Forest<P::Action, P::Observation>, target: usize) {
+    let mut threshold = 1;
+    while forest.retained_size(threshold) > target && threshold < u32::MAX / 2 {
+      threshold *= 2;
+    }
+    let remap = forest.compact(threshold);
+    self.particles.remap(&remap);
+  }
+
   fn backpropogate(
     &self,
     forest: &mut Forest<P::Action, P::Observation>,
     bounds: &mut Vec<Bounds>,
     trajectory: &Trajectory<P::Action>,
-    mut values: Vec<f32>,
+    values: Vec<f32>,
+    terminal: bool,
+    // actions played after the end of the trajectory, by the expansion
+    played: &[(usize, P::Action)],
   ) {
+    // proof of the position the trajectory ends in, if it's known
+    let mut child_proof = if self.solver {
+      trajectory
+        .current_
+        .iter()
+        .find_map(|nid| forest.node(*nid).proof.clone())
+        .or_else(|| terminal.then(|| Proof::terminal(trajectory.current_.len())))
+    } else {
+      None
+    };
+    let mut child_bounds = if self.score_bounded {
+      trajectory
+        .current_
+        .iter()
+        .find_map(|nid| forest.node(*nid).value_bounds.clone())
+        .or_else(|| terminal.then(|| vec![Bounds::new_known(0.0, 0.0); trajectory.current_.len()]))
+    } else {
+      None
+    };
+    let mut played: BTreeSet<_> = played.iter().cloned().collect();
+    let mut values = self.backed_up(values);
     // add this value sample to the trajectory's current nodes
     for (ix, nid) in trajectory.current_.iter().enumerate() {
       let node = forest.node_mut(*nid);
       node.value.add_sample(values[ix], 1);
       bounds[ix].update_bounds(values[ix]);
+      if self.amaf {
+        Self::update_amaf(node, ix, &played, values[ix]);
+      }
     }
     //print!("values: {values:?} agents in backprop: ");
-    for (nids, (agent, action)) in trajectory.branch.iter().rev() {
+    let (visits, losses) = self.virtual_loss.map_or((0, 0), |v| v.amounts());
+    let mut children = trajectory.current_.clone();
+    for (depth, (nids, (agent, action))) in trajectory.branch.iter().enumerate().rev() {
       //print!(" {agent}");
+      let rewards = self.backed_up(nids.iter().map(|(_, r)| *r).collect());
       for ix in 0..nids.len() {
+        // the child can be shared with other parents, its value already
+        // includes this sample
+        let child_value = forest.node(children[ix]).value.value();
         let node = forest.node_mut(nids[ix].0);
         if ix == *agent {
           //print!(" up {}", nids[ix].1);
+          let sample = rewards[ix] + self.discounts[ix] * values[ix];
           let data = node.actions.get_mut(action).unwrap();
-          data.action_reward.add_sample(nids[ix].1, 1);
-          data.value_of_next_state.add_sample(values[ix], 1);
+          data.remove_virtual(visits, losses);
+          data.action_reward.add_sample(rewards[ix], 1);
+          data
+            .value_of_next_state
+            .add_sample(self.discounts[ix] * values[ix], 1);
+          data.returns.add_sample(sample, 1);
+          if self.transpositions {
+            data
+              .value_of_next_state
+              .set_value(self.discounts[ix] * child_value);
+          }
+          let position = Position {
+            agent: ix,
+            depth: depth as u32,
+          };
+          self.bandit_policy.update(
+            node,
+            action,
+            sample,
+            &trajectory.selections[depth],
+            &bounds[ix],
+            position,
+          );
+        }
+        if ix == *agent && self.chance_nodes {
+          self.update_expectation(forest, nids[ix].0, action, ix);
         }
+        let node = forest.node_mut(nids[ix].0);
 
-        values[ix] += nids[ix].1;
+        values[ix] = rewards[ix] + self.discounts[ix] * values[ix];
         node.value.add_sample(values[ix], 1);
         bounds[ix].update_bounds(values[ix]);
+        children[ix] = nids[ix].0;
+      }
+
+      if let Some((_, replier)) = trajectory.repliers.iter().find(|(d, _)| *d == depth) {
+        let node = forest.node_mut(nids[*agent].0);
+        if node.repliers.len() <= *replier {
+          node.repliers.resize_with(replier + 1, RunningAverage::new);
+        }
+        node.repliers[*replier].add_sample(values[*agent], 1);
+      }
+
+      if self.amaf {
+        played.insert((*agent, action.clone()));
+        let node = forest.node_mut(nids[*agent].0);
+        Self::update_amaf(node, *agent, &played, values[*agent]);
+      }
+
+      if self.solver {
+        let node = forest.node_mut(nids[*agent].0);
+        if let Some(proof) = child_proof {
+          let data = node.actions.get_mut(action).unwrap();
+          if data.proof.is_none() {
+            let rewards: Vec<_> = nids.iter().map(|(_, r)| *r).collect();
+            data.proof = Some(proof.through(*agent, &rewards, &self.discounts));
+            node.update_proof();
+          }
+        }
+        child_proof = node.proof.clone();
+      }
+
+      if self.score_bounded {
+        let node = forest.node_mut(nids[*agent].0);
+        if let Some(bounds) = child_bounds {
+          node.actions.get_mut(action).unwrap().value_bounds = Some(
+            bounds
+              .iter()
+              .zip(nids.iter().zip(self.discounts.iter()))
+              .map(|(b, ((_, r), d))| Bounds::new_known(r + d * b.low(), r + d * b.high()))
+              .collect(),
+          );
+          node.update_value_bounds(*agent, self.value_bounds.as_ref().unwrap());
+        }
+        child_bounds = node.value_bounds.clone();
       }
     }
     //println!();
   }
 
+  // the rewards or values each agent backs up, see `Backup`
+  fn backed_up(&self, mut values: Vec<f32>) -> Vec<f32> {
+    if self.backup != Backup::MaxN {
+      let root = values[self.root_agent];
+      for (ix, v) in values.iter_mut().enumerate() {
+        if ix != self.root_agent {
+          *v = -root;
+        }
+      }
+    }
+    values
+  }
+
+  // whether the agent to act at the node selects with the bandit, in best
+  // reply search the replier is selected at the first opponent move of every
+  // round
+  fn replies(
+    &self,
+    trajectory: &mut Trajectory<P::Action>,
+    agent: usize,
+    node: &Node<P::Action, P::Observation>,
+    bounds: &Bounds,
+  ) -> bool {
+    if self.backup != Backup::BestReply || agent == self.root_agent {
+      return true;
+    }
+    let opponents = trajectory.current_.len() - 1;
+    let moved = trajectory
+      .branch
+      .iter()
+      .rev()
+      .take_while(|(_, (a, _))| *a != self.root_agent)
+      .count();
+    if moved % opponents == 0 {
+      let replier = Self::select_replier(node, opponents, bounds);
+      trajectory.repliers.push((trajectory.branch.len(), replier));
+    }
+    trajectory
+      .repliers
+      .last()
+      .is_some_and(|(_, replier)| moved % opponents == *replier)
+  }
+
+  // ucb1 on the values the repliers got at the node, untried ones first
+  fn select_replier(
+    node: &Node<P::Action, P::Observation>,
+    opponents: usize,
+    bounds: &Bounds,
+  ) -> usize {
+    let total: u32 = node.repliers.iter().map(|r| r.count()).sum();
+    let score = |replier: usize| match node.repliers.get(replier) {
+      Some(r) if r.count() > 0 => {
+        let exploration = ((total as f32).ln() / r.count() as f32).sqrt();
+        bounds.normalise(r.value()) + std::f32::consts::SQRT_2 * exploration
+      }
+      _ => f32::INFINITY,
+    };
+    (0..opponents)
+      .max_by(|x, y| score(*x).total_cmp(&score(*y)))
+      .unwrap()
+  }
+
+  // the candidate action with the highest prior, the first one of the ties
+  fn ordered_action(
+    node: &Node<P::Action, P::Observation>,
+    available: Option<&BTreeSet<P::Action>>,
+  ) -> P::Action {
+    node
+      .candidate_actions(available)
+      .min_by(|(_, x), (_, y)| y.static_policy_score.total_cmp(&x.static_policy_score))
+      .unwrap()
+      .0
+      .clone()
+  }
+
   pub fn create_workers(&self, count: usize) -> Vec<Worker<P::HiddenState, P::Action>> {
     let guard = self.forest.write().unwrap();
     let mut result = Vec::with_capacity(count);
     for _ in 0..count {
-      result.push(Worker {
-        states_in_flight: self
+      let mut rng = self.seed.map(|seed| {
+        let n = self.workers_created.fetch_add(1, Ordering::Relaxed);
+        StdRng::seed_from_u64(seed.wrapping_add(n))
+      });
+      let states_in_flight = Self::seeded(&mut rng, || {
+        self
           .problem
-          .sample_h_state_batched(&self.b_state, self.block_size as usize),
+          .sample_h_state_batched(&self.b_state, self.block_size as usize)
+      });
+      result.push(Worker {
+        states_in_flight,
         trajectories_in_flight: vec![self.empty_trajectory(&guard); self.block_size as usize],
         trajectories_awaiting_expansion: vec![],
         states_awaiting_expansion: vec![],
         trajectories_awaiting_backprop: vec![],
+        epoch: guard.epoch(),
+        rng,
       });
     }
     result
   }
 
-  pub fn get_policy(&self) -> Vec<(P::Action, f32, f32)> {
+  // runs `f` with `rng`, if any, as the generator of the thread
+  fn seeded<T>(rng: &mut Option<StdRng>, f: impl FnOnce() -> T) -> T {
+    match rng {
+      Some(rng) => random::with_rng(rng, f),
+      None => f(),
+    }
+  }
+
+  // drops all the work in flight, used when the worker's node ids are stale
+  fn reset_worker(
+    &self,
+    forest_g: &Forest<P::Action, P::Observation>,
+    worker: &mut Worker<P::HiddenState, P::Action>,
+  ) {
+    worker.states_in_flight = self
+      .problem
+      .sample_h_state_batched(&self.b_state, self.block_size as usize);
+    worker.trajectories_in_flight = vec![self.empty_trajectory(forest_g); self.block_size as usize];
+    worker.states_awaiting_expansion.clear();
+    worker.trajectories_awaiting_expansion.clear();
+    worker.trajectories_awaiting_backprop.clear();
+    worker.epoch = forest_g.epoch();
+  }
+
+  // the candidate action of the root with the given visits since the root was
+  // set and the highest gumbel perturbed logit plus completed value, or the
+  // highest overall when none has them, as can happen with trajectories in
+  // flight
+  fn gumbel_select(
+    &self,
+    gumbel: &Gumbel,
+    node: &Node<P::Action, P::Observation>,
+    available: Option<&BTreeSet<P::Action>>,
+  ) -> P::Action {
+    let completed: BTreeMap<_, _> = node
+      .actions
+      .keys()
+      .zip(node.completed_values(gumbel.c_visit, gumbel.c_scale))
+      .collect();
+    let mut noise = self.gumbel_noise.lock().unwrap();
+    let scores: Vec<_> = node
+      .candidate_actions(available)
+      .map(|(a, data)| {
+        let n = data.virtual_select_count();
+        // the visits the action had when the root was set don't count
+        let (g, base) = *noise
+          .entry(a.clone())
+          .or_insert_with(|| (sample_gumbel(&mut random::rng()), n));
+        (a, n.saturating_sub(base), g + data.logit() + completed[a])
+      })
+      .collect();
+    let t: u32 = scores.iter().map(|(_, n, _)| n).sum();
+    let visits = {
+      let considered = gumbel.considered.min(scores.len());
+      let mut halving = self.halving.lock().unwrap();
+      if halving.0 != considered || halving.1.len() <= t as usize {
+        let len = (t + 1).max(gumbel.simulations) as usize;
+        *halving = (
+          considered,
+          considered_visits(considered, gumbel.simulations, len),
+        );
+      }
+      halving.1[t as usize]
+    };
+    let best = |with_visits: bool| {
+      scores
+        .iter()
+        .filter(|(_, n, _)| !with_visits || *n == visits)
+        .max_by(|x, y| x.2.total_cmp(&y.2))
+        .map(|(a, ..)| (*a).clone())
+    };
+    best(true).or_else(|| best(false)).unwrap()
+  }
+
+  // the action gumbel search settled on, the candidate of the most visited
+  // since the root was set with the highest gumbel perturbed logit plus
+  // completed value
+  pub fn gumbel_action(&self) -> Option<P::Action> {
+    let gumbel = self.gumbel?;
     let guard = self.forest.read().unwrap();
-    let agent_ix = self
+    let root = guard.node(guard.roots()[self.root_agent]);
+    let completed: BTreeMap<_, _> = root
+      .actions
+      .keys()
+      .zip(root.completed_values(gumbel.c_visit, gumbel.c_scale))
+      .collect();
+    let noise = self.gumbel_noise.lock().unwrap();
+    let scores: Vec<_> = root
+      .candidate_actions(None)
+      .map(|(a, data)| {
+        let (g, base) = noise.get(a).copied().unwrap_or((0.0, 0));
+        let n = data.select_count().saturating_sub(base);
+        (a, n, g + data.logit() + completed[a])
+      })
+      .collect();
+    let most = scores.iter().map(|(_, n, _)| *n).max()?;
+    scores
+      .into_iter()
+      .filter(|(_, n, _)| *n == most)
+      .max_by(|x, y| x.2.total_cmp(&y.2))
+      .map(|(a, ..)| a.clone())
+  }
+
+  fn add_root_noise(&self, forest: &mut Forest<P::Action, P::Observation>) {
+    let noise = match self.root_noise {
+      Some(noise) => noise,
+      None => return,
+    };
+    let root = forest.node_mut(forest.roots()[self.root_agent]);
+    if !root.actions_created() || self.root_noised.swap(true, Ordering::Relaxed) {
+      return;
+    }
+    let eta = sample_dirichlet(noise.alpha, root.actions.len(), &mut random::rng());
+    for (data, eta) in root.actions.values_mut().zip(eta) {
+      data.static_policy_score =
+        (1.0 - noise.epsilon) * data.static_policy_score + noise.epsilon * eta;
+    }
+    if self.action_widening.is_some() {
+      root.rank_actions();
+    }
+  }
+
+  // creates the actions of a new node, or adds the ones it's missing
+  fn create_actions(&self, node: &mut Node<P::Action, P::Observation>, state: &P::HiddenState) {
+    let actions = self.problem.legal_actions(state);
+    if node.actions_created() {
+      node.add_actions(actions);
+    } else {
+      node.create_actions(actions);
+    }
+    if let Some(widening) = self.action_widening {
+      node.widened = widening.limit(node.select_count());
+    }
+  }
+
+  // whether the node lacks some of the legal actions of `state`, which only
+  // happens in information set searches
+  fn has_missing_actions(
+    &self,
+    node: &Node<P::Action, P::Observation>,
+    state: &P::HiddenState,
+  ) -> bool {
+    self.information_sets.is_some()
+      && self
+        .problem
+        .legal_actions(state)
+        .iter()
+        .any(|a| !node.actions.contains_key(a))
+  }
+
+  // index of the agent whose observations key the children of every tree
+  fn observer(&self, ix: usize) -> usize {
+    match self.information_sets {
+      Some(InformationSets::SingleObserver) => self.root_agent,
+      _ => ix,
+    }
+  }
+
+  // resamples the outcome of `action` from the state before it, if it would
+  // reach a new child past the widening limit of the action
+  fn widen_outcome(
+    &self,
+    forest: &Forest<P::Action, P::Observation>,
+    node_id: NodeId,
+    (observer, action): (usize, &P::Action),
+    widening: Widening,
+    (pre_state, state): (&P::HiddenState, &mut P::HiddenState),
+    outcome: &mut Vec<(f32, P::Observation)>,
+  ) {
+    let node = forest.node(node_id);
+    let data = &node.actions[action];
+    if node.has_child(&outcome[observer].1) || data.outcomes < widening.limit(data.select_count()) {
+      return;
+    }
+    for _ in 0..MAX_RESAMPLES {
+      let mut resampled = pre_state.clone();
+      let resampled_outcome = self.problem.apply_action(&mut resampled, action);
+      if node.has_child(&resampled_outcome[observer].1) {
+        *state = resampled;
+        *outcome = resampled_outcome;
+        return;
+      }
+    }
+  }
+
+  // probability of the outcomes of `action` in which the agent at `agent_ix`
+  // observes `o`, along with their expected reward
+  fn chance_of(
+    &self,
+    state: &P::HiddenState,
+    action: &P::Action,
+    agent_ix: usize,
+    o: &P::Observation,
+  ) -> Option<(f32, f32)> {
+    let (probability, reward) = self
       .problem
-      .agent_to_act(&self.problem.sample_h_state(&self.b_state))
-      .into() as usize;
-    let root_id = guard.roots()[agent_ix];
+      .chance_outcomes(state, action)?
+      .into_iter()
+      .filter(|(_, outcome)| outcome[agent_ix].1 == *o)
+      .fold((0.0, 0.0), |(p, r), (po, outcome)| {
+        (p + po, r + po * outcome[agent_ix].0)
+      });
+    (probability > 0.0).then(|| (probability, reward / probability))
+  }
+
+  // sets the value of an action from the values of the children reached
+  // through it, weighted by their probabilities
+  fn update_expectation(
+    &self,
+    forest: &mut Forest<P::Action, P::Observation>,
+    node_id: NodeId,
+    action: &P::Action,
+    ix: usize,
+  ) {
+    let (mut total, mut reward, mut value) = (0.0, 0.0, 0.0);
+    for edge in forest.node(node_id).actions[action].chance.iter() {
+      let child = &forest.node(edge.child).value;
+      if child.count() > 0 {
+        total += edge.probability;
+        reward += edge.probability * edge.reward;
+        value += edge.probability * child.value();
+      }
+    }
+    if total > 0.0 {
+      let data = forest.node_mut(node_id).actions.get_mut(action).unwrap();
+      data.action_reward.set_value(reward / total);
+      data
+        .value_of_next_state
+        .set_value(self.discounts[ix] * value / total);
+    }
+  }
+
+  // adds `value` to the AMAF statistics of the actions `agent` played
+  fn update_amaf(
+    node: &mut Node<P::Action, P::Observation>,
+    agent: usize,
+    played: &BTreeSet<(usize, P::Action)>,
+    value: f32,
+  ) {
+    for (_, action) in played.iter().filter(|(ix, _)| *ix == agent) {
+      if let Some(data) = node.actions.get_mut(action) {
+        data.amaf.add_sample(value, 1);
+      }
+    }
+  }
+
+  // values of a node whose result is already known, trajectories reaching it
+  // are backpropagated without descending further
+  fn known_values(&self, node: &Node<P::Action, P::Observation>) -> Option<Vec<f32>> {
+    if let (true, Some(proof)) = (self.solver, &node.proof) {
+      return Some(proof.values.clone());
+    }
+    match (self.score_bounded, &node.value_bounds) {
+      (true, Some(bounds)) if bounds.iter().all(|b| b.low() == b.high()) => {
+        Some(bounds.iter().map(Bounds::low).collect())
+      }
+      _ => None,
+    }
+  }
+
+  // reverts the virtual loss of every trajectory the worker still holds, so
+  // that the statistics are exact once the search returns
+  fn revert_worker(
+    &self,
+    forest_g: &Forest<P::Action, P::Observation>,
+    worker: &mut Worker<P::HiddenState, P::Action>,
+  ) {
+    let (visits, losses) = self.virtual_loss.map_or((0, 0), |v| v.amounts());
+    for trajectory in worker
+      .trajectories_in_flight
+      .iter()
+      .chain(worker.trajectories_awaiting_expansion.iter())
+      .chain(worker.trajectories_awaiting_backprop.iter().map(|(t, _)| t))
+    {
+      for (nids, (agent, action)) in trajectory.branch.iter() {
+        forest_g.node(nids[*agent].0).actions[action].remove_virtual(visits, losses);
+      }
+    }
+    self.reset_worker(forest_g, worker);
+  }
+
+  pub fn get_policy(&self) -> Vec<PolicyEntry<P::Action>> {
+    let guard = self.forest.read().unwrap();
+    let root_id = guard.roots()[self.root_agent];
     let root = guard.node(root_id);
     let root_s_count = root.select_count() as f32;
+    let (c_visit, c_scale) = self
+      .gumbel
+      .map_or((C_VISIT, C_SCALE), |g| (g.c_visit, g.c_scale));
+    let improved = root.improved_policy(c_visit, c_scale);
     root
       .actions
       .iter()
-      .map(|(a, data)| {
-        (
-          a.clone(),
-          data.select_count() as f32 / root_s_count,
-          data.value(),
-        )
+      .zip(improved)
+      .map(|((a, data), improved)| PolicyEntry {
+        action: a.clone(),
+        probability: data.select_count() as f32 / root_s_count,
+        select_count: data.select_count(),
+        value: data.value(),
+        improved,
+        proof: data.proof.clone(),
       })
       .collect()
   }
 
+  // most selected path of every agent's tree, see `forest::cursor` to walk
+  // the trees further
+  pub fn principal_variations(&self) -> Vec<Vec<PvStep<P::Action, P::Observation>>> {
+    let guard = self.forest.read().unwrap();
+    guard
+      .roots()
+      .into_iter()
+      .map(|root| guard.cursor(root).principal_variation())
+      .collect()
+  }
+
+  pub fn info(&self) -> SearchInfo<P::Action, P::Observation> {
+    let guard = self.forest.read().unwrap();
+    let elapsed = self
+      .started
+      .get()
+      .map(|s| s.elapsed())
+      .unwrap_or(Duration::ZERO);
+    self.info_of(&guard, elapsed)
+  }
+
+  fn info_of(
+    &self,
+    forest_g: &Forest<P::Action, P::Observation>,
+    elapsed: Duration,
+  ) -> SearchInfo<P::Action, P::Observation> {
+    let root_id = forest_g.roots()[self.root_agent];
+    let root = forest_g.node(root_id);
+    let root_actions: Vec<_> = root
+      .actions
+      .iter()
+      .map(|(a, data)| (a.clone(), data.select_count(), data.value()))
+      .collect();
+    let best_action = root_actions
+      .iter()
+      .max_by_key(|(_, c, _)| *c)
+      .map(|(a, ..)| a.clone());
+    let select_count = forest_g.node(forest_g.roots()[0]).select_count() - self.root_visits;
+    SearchInfo {
+      select_count,
+      elapsed,
+      nodes_per_sec: select_count as f32 / elapsed.as_secs_f32().max(f32::EPSILON),
+      root_actions,
+      best_action,
+      pv: forest_g.principal_variation(root_id),
+    }
+  }
+
+  fn report(&self, forest_g: &Forest<P::Action, P::Observation>, started: Instant) {
+    if let Some(reporter) = &self.reporter {
+      // skip if another worker is already reporting
+      if let Ok(mut last) = reporter.last.try_lock() {
+        let now = Instant::now();
+        if last.map(|l| now - l >= reporter.interval).unwrap_or(true) {
+          *last = Some(now);
+          (reporter.callback)(self.info_of(forest_g, now - started));
+        }
+      }
+    }
+  }
+
+  fn progress(
+    &self,
+    forest_g: &Forest<P::Action, P::Observation>,
+    started: Instant,
+  ) -> SearchProgress {
+    let roots = forest_g.roots();
+    let mut top_counts = (0, 0);
+    let (mut robust_value, mut max_value) = (f32::MIN, f32::MIN);
+    for data in forest_g.node(roots[self.root_agent]).actions.values() {
+      let c = data.select_count();
+      if c > top_counts.0 {
+        top_counts = (c, top_counts.0);
+        robust_value = data.value();
+      } else if c > top_counts.1 {
+        top_counts.1 = c;
+      }
+      if c > 0 {
+        max_value = max_value.max(data.value());
+      }
+    }
+    SearchProgress {
+      select_count: forest_g.node(roots[0]).select_count() - self.root_visits,
+      forest_size: forest_g.size(),
+      max_depth: self.max_depth.load(Ordering::Relaxed),
+      elapsed: started.elapsed(),
+      top_counts,
+      max_robust: top_counts.0 > 0 && robust_value >= max_value,
+    }
+  }
+
   fn empty_trajectory(
     &self,
     forest_g: &Forest<P::Action, P::Observation>,
@@ -350,6 +1631,8 @@ where
     Trajectory {
       current_: forest_g.roots(),
       branch: vec![],
+      selections: vec![],
+      repliers: vec![],
     }
   }
 
@@ -360,6 +1643,68 @@ where
   ) {
     trajectory.current_ = forest_g.roots();
     trajectory.branch = vec![];
+    trajectory.selections = vec![];
+    trajectory.repliers = vec![];
+  }
+}
+
+impl<P: MctsProblem, B, E> SearchBackend<P> for Search<P, B, E>
+where
+  B: Bandit<P::HiddenState, P::Action, P::Observation>,
+  P::HiddenState: Clone,
+  E: Expansion<P>,
+{
+  type Worker = Worker<P::HiddenState, P::Action>;
+
+  fn create_workers(&self, count: usize) -> Vec<Self::Worker> {
+    self.create_workers(count)
+  }
+
+  fn start(&self, worker: &mut Self::Worker) {
+    self.start(worker)
+  }
+
+  fn get_policy(&self) -> Vec<PolicyEntry<P::Action>> {
+    self.get_policy()
+  }
+
+  fn stop_handle(&self) -> StopHandle {
+    self.stop_handle()
+  }
+}
+
+impl Widening {
+  fn limit(&self, n: u32) -> u32 {
+    (self.k * (n as f32).powf(self.alpha)).ceil().max(1.0) as u32
+  }
+}
+
+impl VirtualLoss {
+  // (virtual visits, virtual losses) added per trajectory in flight
+  fn amounts(&self) -> (u32, u32) {
+    match self {
+      VirtualLoss::Loss(n) => (*n, *n),
+      VirtualLoss::Visits(n) => (*n, 0),
+    }
+  }
+}
+
+impl StopHandle {
+  pub fn new() -> Self {
+    StopHandle(Arc::new(AtomicBool::new(false)))
+  }
+
+  pub fn stop(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+
+  // allows the search to be started again
+  pub fn reset(&self) {
+    self.0.store(false, Ordering::Relaxed);
+  }
+
+  pub fn is_stopped(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
   }
 }
 
@@ -368,3 +1713,21 @@ impl<A: Clone + Debug> Debug for Trajectory<A> {
     write!(f, "{:?}", self.current_)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::considered_visits;
+
+  #[test]
+  fn test_considered_visits() {
+    // two phases of 8 simulations, 4 actions visited twice then 2 actions
+    // visited four times
+    assert_eq!(
+      considered_visits(4, 16, 16),
+      vec![0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5]
+    );
+    // past the budget the last two actions alternate
+    assert_eq!(considered_visits(4, 16, 20)[16..], [6, 6, 7, 7]);
+    assert_eq!(considered_visits(1, 16, 3), vec![0, 1, 2]);
+  }
+}
@@ -0,0 +1,135 @@
+use std::{
+  cmp::Ordering,
+  fmt::{self, Display},
+};
+
+// proven results for two player games, MCTS-Solver style
+// a proof on a node is the result of optimal play for the agent to act there,
+// a proof on an action is the result of optimal play after taking it, for the
+// agent taking it
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+  Loss,
+  Draw,
+  Win,
+}
+
+#[derive(Clone, Debug)]
+pub struct Proof {
+  pub outcome: Outcome,
+  // number of actions until the end of the game, so a win with depth 1 is
+  // won by the next action
+  pub depth: u32,
+  // return of every agent under optimal play
+  pub(crate) values: Vec<f32>,
+}
+
+impl Outcome {
+  // result for `agent`, given the returns of both agents
+  fn of(agent: usize, values: &[f32]) -> Self {
+    match values[agent].total_cmp(&values[1 - agent]) {
+      Ordering::Less => Outcome::Loss,
+      Ordering::Equal => Outcome::Draw,
+      Ordering::Greater => Outcome::Win,
+    }
+  }
+}
+
+impl Proof {
+  pub(crate) fn terminal(agent_count: usize) -> Self {
+    Proof {
+      outcome: Outcome::Draw,
+      depth: 0,
+      values: vec![0.0; agent_count],
+    }
+  }
+
+  // proof of the action taken by `agent`, that emitted `rewards` and led to
+  // the position proven by `self`
+  pub(crate) fn through(&self, agent: usize, rewards: &[f32], discounts: &[f32]) -> Self {
+    let values: Vec<_> = self
+      .values
+      .iter()
+      .zip(rewards.iter().zip(discounts))
+      .map(|(v, (r, d))| r + d * v)
+      .collect();
+    Proof {
+      outcome: Outcome::of(agent, &values),
+      depth: self.depth + 1,
+      values,
+    }
+  }
+
+  // faster wins and slower losses are better
+  pub(crate) fn cmp_for_agent(&self, other: &Proof) -> Ordering {
+    self
+      .outcome
+      .cmp(&other.outcome)
+      .then_with(|| match self.outcome {
+        Outcome::Win => other.depth.cmp(&self.depth),
+        Outcome::Loss => self.depth.cmp(&other.depth),
+        Outcome::Draw => Ordering::Equal,
+      })
+  }
+}
+
+impl Display for Proof {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let outcome = match self.outcome {
+      Outcome::Loss => "loss",
+      Outcome::Draw => "draw",
+      Outcome::Win => "win",
+    };
+    write!(f, "forced {} in {}", outcome, self.depth)
+  }
+}
+
+// proof of a node given the proofs of its actions, if the proven actions
+// decide it
+pub(crate) fn prove<'a>(proofs: impl Iterator<Item = Option<&'a Proof>>) -> Option<Proof> {
+  let mut best: Option<&Proof> = None;
+  let mut complete = true;
+  for proof in proofs {
+    match proof {
+      Some(p) => {
+        if best.is_none_or(|b| p.cmp_for_agent(b) == Ordering::Greater) {
+          best = Some(p);
+        }
+      }
+      None => complete = false,
+    }
+  }
+  best
+    .filter(|b| complete || b.outcome == Outcome::Win)
+    .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_prove() {
+    let terminal = Proof::terminal(2);
+    let win = terminal.through(0, &[1.0, 0.0], &[1.0; 2]);
+    let draw = terminal.through(0, &[0.0, 0.0], &[1.0; 2]);
+    assert_eq!(win.outcome, Outcome::Win);
+    assert_eq!(draw.outcome, Outcome::Draw);
+
+    // the opponent is to act after the loss, which it wins in 1
+    let loss = win.through(1, &[0.0, 0.0], &[1.0; 2]);
+    assert_eq!(loss.outcome, Outcome::Loss);
+    assert_eq!(loss.depth, 2);
+
+    assert!(prove([Some(&draw), None].into_iter()).is_none());
+    assert_eq!(
+      prove([Some(&draw), Some(&loss)].into_iter())
+        .unwrap()
+        .outcome,
+      Outcome::Draw
+    );
+    let p = prove([None, Some(&loss), Some(&win)].into_iter()).unwrap();
+    assert_eq!((p.outcome, p.depth), (Outcome::Win, 1));
+  }
+}
@@ -4,15 +4,40 @@ use std::{
 };
 
 use futures::executor::block_on;
-use lib::MctsProblem;
+use lib::{random, MctsProblem};
 use mcts::{
   bandits::{Bandit, GreedyBandit},
-  search::Search,
-  Expansion, SearchLimit,
+  search::{Gumbel, RootNoise, Search},
+  Expansion, FinalSelection, SearchLimit,
 };
-use rand::{distributions::WeightedIndex, prelude::Distribution};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
+// how `playout` searches and picks the moves it plays
+#[derive(Clone, Copy, Debug)]
+pub struct PlayoutOptions {
+  pub selection: FinalSelection,
+  // mixed into the root priors of every move, for diverse self play games
+  pub root_noise: Option<RootNoise>,
+  // searches the root by sequential halving and plays the action it settles
+  // on. it takes precedence over `selection`
+  pub gumbel: Option<Gumbel>,
+  // every random choice of the playout is drawn from `lib::random::rng`, so
+  // that it's reproduced by the same seed
+  pub seed: Option<u64>,
+}
+
+impl Default for PlayoutOptions {
+  fn default() -> Self {
+    Self {
+      selection: FinalSelection::RobustChild,
+      root_noise: None,
+      gumbel: None,
+      seed: None,
+    }
+  }
+}
+
 pub fn playout<
   P: MctsProblem,
   B: Bandit<P::HiddenState, P::Action, P::Observation>,
@@ -25,7 +50,7 @@ pub fn playout<
   bandit_policy: B,
   mut horizon: u32,
   node_init: E,
-  best_only: bool,
+  options: PlayoutOptions,
 ) -> Vec<PlayoutStep<P::Agent, P::Action, P::Observation>>
 where
   P::HiddenState: Clone + Debug,
@@ -33,15 +58,12 @@ where
   P::Action: Debug,
   P::BeliefState: Clone + Debug,
 {
-  let mut h_state = problem.sample_h_state(b_state);
-
-  let mut result = vec![];
-  while horizon != 0 && !problem.check_terminal(&h_state) {
-    //println!("{:?}", h_state);
-    //println!("{:?}", b_state);
-    horizon -= 1;
+  let play = || {
+    let mut h_state = problem.sample_h_state(b_state);
 
-    let search = Search::new(
+    // the tree is reused across moves, only the subtree below the played
+    // action and the received observations is kept
+    let mut search = Search::new(
       problem.clone(),
       Arc::new(b_state.clone()),
       block_size,
@@ -49,34 +71,55 @@ where
       bandit_policy,
       node_init,
     );
-    let mut workers = search.create_workers(1);
-    search.start(&mut workers[0]);
-    let computed_policy = search.get_policy();
-    let selected_action = if best_only {
-      computed_policy
+    if let Some(noise) = options.root_noise {
+      search = search.with_root_noise(noise);
+    }
+    if let Some(gumbel) = options.gumbel {
+      search = search.with_gumbel(gumbel);
+    }
+    if options.seed.is_some() {
+      search = search.with_seed(random::rng().gen());
+    }
+    let mut result = vec![];
+    while horizon != 0 && !problem.check_terminal(&h_state) {
+      //println!("{:?}", h_state);
+      //println!("{:?}", b_state);
+      horizon -= 1;
+
+      let mut workers = search.create_workers(1);
+      search.start(&mut workers[0]);
+      let policy = search.get_policy();
+      let selected_action = match search.gumbel_action() {
+        Some(action) => action,
+        None => options.selection.select(&policy, result.len() as u32),
+      };
+      let computed_policy: Vec<_> = policy
+        .into_iter()
+        .map(|entry| (entry.action, entry.probability, entry.value, entry.improved))
+        .collect();
+      //println!("playing: {:?}", selected_action);
+      let current_agent_ix = problem.agent_to_act(&h_state).into() as usize;
+      let rewards_and_observations = problem.apply_action(&mut h_state, &selected_action);
+      //println!("observed {:?}", rewards_and_observations);
+      problem.belief_update(b_state, &rewards_and_observations[current_agent_ix].1);
+      let observations: Vec<_> = rewards_and_observations
         .iter()
-        .max_by(|(_, a, _), (_, b, _)| a.total_cmp(b))
-        .map(|(action, ..)| action.clone())
-        .unwrap()
-    } else {
-      let index = WeightedIndex::new(computed_policy.iter().map(|(_, w, _)| w)).unwrap();
-      computed_policy[index.sample(&mut rand::thread_rng())]
-        .0
-        .clone()
-    };
-    //println!("playing: {:?}", selected_action);
-    let current_agent_ix = problem.agent_to_act(&h_state).into() as usize;
-    let rewards_and_observations = problem.apply_action(&mut h_state, &selected_action);
-    //println!("observed {:?}", rewards_and_observations);
-    problem.belief_update(b_state, &rewards_and_observations[current_agent_ix].1);
-    result.push(PlayoutStep {
-      current_agent: problem.agent_to_act(&h_state),
-      computed_policy,
-      selected_action,
-      rewards_and_observations,
-    });
+        .map(|(_, o)| o.clone())
+        .collect();
+      search.advance(Arc::new(b_state.clone()), &selected_action, &observations);
+      result.push(PlayoutStep {
+        current_agent: problem.agent_to_act(&h_state),
+        computed_policy,
+        selected_action,
+        rewards_and_observations,
+      });
+    }
+    result
+  };
+  match options.seed {
+    Some(seed) => random::with_rng(&mut StdRng::seed_from_u64(seed), play),
+    None => play(),
   }
-  result
 }
 
 pub fn accumulate_rewards<P: MctsProblem>(
@@ -84,12 +127,13 @@ pub fn accumulate_rewards<P: MctsProblem>(
   playout: &Vec<PlayoutStep<P::Agent, P::Action, P::Observation>>,
 ) -> Vec<f32> {
   let mut result = vec![0.0; problem.agents().len()];
-  let mut factor = 1.0;
+  let discounts = problem.discounts();
+  let mut factors = vec![1.0; result.len()];
   for step in playout.iter() {
     for ix in 0..result.len() {
-      result[ix] += factor * step.rewards_and_observations[ix].0;
+      result[ix] += factors[ix] * step.rewards_and_observations[ix].0;
+      factors[ix] *= discounts[ix];
     }
-    factor = problem.discount();
   }
   result
 }
@@ -97,7 +141,8 @@ pub fn accumulate_rewards<P: MctsProblem>(
 #[derive(Serialize, Deserialize)]
 pub struct PlayoutStep<Ag, Ac, O> {
   current_agent: Ag,
-  computed_policy: Vec<(Ac, f32, f32)>,
+  // action, share of the visits, value and completed q improved policy
+  computed_policy: Vec<(Ac, f32, f32, f32)>,
   selected_action: Ac,
   rewards_and_observations: Vec<(f32, O)>,
 }
@@ -119,9 +164,11 @@ mod test {
 
   use examples::prob2;
   use lib::MctsProblem;
-  use mcts::{bandits::Uct, EmptyInit, SearchLimit};
+  use mcts::{
+    bandits::Uct, search::Gumbel, EmptyInit, FinalSelection, SearchLimit, TemperatureSchedule,
+  };
 
-  use super::playout;
+  use super::{playout, PlayoutOptions};
 
   #[test]
   fn t1() {
@@ -129,7 +176,67 @@ mod test {
     let mut start = m.start_state();
     let limit = SearchLimit::new(64);
     let bandit_policy = Uct(1.8);
-    let t = playout(m, &mut start, 1, limit, bandit_policy, 20, EmptyInit, false);
+    let t = playout(
+      m,
+      &mut start,
+      1,
+      limit,
+      bandit_policy,
+      20,
+      EmptyInit,
+      PlayoutOptions {
+        selection: FinalSelection::Temperature(TemperatureSchedule::Constant(1.0)),
+        ..Default::default()
+      },
+    );
     println!("{:?}", t);
   }
+
+  #[test]
+  fn t2() {
+    let m = Arc::new(prob2());
+    let mut start = m.start_state();
+    let t = playout(
+      m,
+      &mut start,
+      1,
+      SearchLimit::new(64),
+      Uct(1.8),
+      20,
+      EmptyInit,
+      PlayoutOptions {
+        gumbel: Some(Gumbel::new(4, 64)),
+        ..Default::default()
+      },
+    );
+    // the improved policy of every move is a distribution
+    for step in t.iter() {
+      let total: f32 = step.computed_policy.iter().map(|p| p.3).sum();
+      assert!((total - 1.0).abs() < 1e-4, "{total}");
+    }
+  }
+
+  #[test]
+  fn test_seeded_playout() {
+    let run = |seed| {
+      let m = Arc::new(prob2());
+      let mut start = m.start_state();
+      let t = playout(
+        m,
+        &mut start,
+        1,
+        SearchLimit::new(64),
+        Uct(1.8),
+        20,
+        EmptyInit,
+        PlayoutOptions {
+          selection: FinalSelection::Temperature(TemperatureSchedule::Constant(1.0)),
+          seed: Some(seed),
+          ..Default::default()
+        },
+      );
+      format!("{t:?}")
+    };
+    assert_eq!(run(7), run(7));
+  }
 }
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use mcts::{bandits::Uct, rollout::RandomRollout, search::Search, SearchLimit};
 use text_io::read;
@@ -39,7 +39,7 @@ fn main() {
       "analyse" => {
         //let lim = read!();
         let lim = 100000;
-        let limit = SearchLimit::new(lim);
+        let limit = SearchLimit::timed(lim, Duration::from_secs(2));
         let search = Search::new(
           game.clone(),
           Arc::new(current_state.clone()),
@@ -51,9 +51,19 @@ fn main() {
         let mut worker = search.create_workers(1);
         search.start(&mut worker[0]);
         let policy = search.get_policy();
-        for (a, s, v) in policy {
-          println!("{a} -> prob {s:.5}, value: {v:.5}");
+        for entry in policy {
+          println!(
+            "{} -> prob {:.5}, value: {:.5}",
+            entry.action, entry.probability, entry.value
+          );
         }
+        let pv = &search.principal_variations()[0];
+        let line: Vec<_> = pv
+          .iter()
+          .filter_map(|step| step.action.as_ref())
+          .map(|a| format!("{} ({}, {:.1})", a.action, a.select_count, a.value))
+          .collect();
+        println!("pv: {}", line.join(" "));
       }
       "exit" | "quit" | "bye" => {
         return;
@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use lib::MctsProblem;
 use mcts::{bandits::Uct, rollout::RandomRollout, search::Search, SearchLimit};
 use text_io::read;
-use tzf8::{Move, State, Tzf8};
+use tzf8::{Move, Observation, State, Tzf8};
 
 fn main() {
   let game = Arc::new(Tzf8);
@@ -18,45 +18,50 @@ fn main() {
   let c: u8 = read!();
   let v: u32 = read!();
   current_state.place(v, 3 - r, c);
+  let lim = 100000;
+  let limit = SearchLimit::timed(lim, Duration::from_secs(2));
+  let mut search = Search::new(
+    game.clone(),
+    Arc::new(current_state.clone()),
+    1,
+    limit,
+    Uct(1.2),
+    RandomRollout(50),
+  )
+  .with_chance_nodes();
   while !game.check_terminal(&current_state) {
     println!("{current_state}");
-    let lim = 100000;
-    let limit = SearchLimit::new(lim);
-    let search = Search::new(
-      game.clone(),
-      Arc::new(current_state.clone()),
-      1,
-      limit,
-      Uct(1.2),
-      RandomRollout(50),
-    );
     let mut worker = search.create_workers(1);
     search.start(&mut worker[0]);
     let policy = search.get_policy();
-    for (a, s, v) in policy {
-      println!("{a} -> prob {s:.5}, value: {v:.5}");
+    for entry in policy {
+      println!(
+        "{} -> prob {:.5}, value: {:.5}",
+        entry.action, entry.probability, entry.value
+      );
     }
     let m: String = read!();
-    match m.as_str() {
-      "l" => {
-        current_state.apply_move(&Move::Left);
-      }
-      "r" => {
-        current_state.apply_move(&Move::Right);
-      }
-      "u" => {
-        current_state.apply_move(&Move::Up);
-      }
-      "d" => {
-        current_state.apply_move(&Move::Down);
-      }
+    let shift = match m.as_str() {
+      "l" => Move::Left,
+      "r" => Move::Right,
+      "u" => Move::Up,
+      "d" => Move::Down,
       _ => {
         panic!("unknown move")
       }
-    }
+    };
+    current_state.apply_move(&shift);
     let r: u8 = read!();
     let c: u8 = read!();
     let v: u32 = read!();
     current_state.place(v, 3 - r, c);
+    // keep the part of the tree below the observed tile
+    let observation = Observation::Result {
+      shift,
+      v: v as u8,
+      x: 3 - r,
+      y: c,
+    };
+    search.advance(Arc::new(current_state.clone()), &shift, &[observation]);
   }
 }
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use lib::MctsProblem;
+use mcts::{bandits::Uct, rollout::RandomRollout, search::Search, SearchLimit};
+use tzf8::Tzf8;
+
+// plays the same number of games with closed and open loop searches of the
+// same budget, and prints the average score and largest tile of each
+fn play(open_loop: bool, iterations: u32) -> (f32, u32) {
+  let game = Arc::new(Tzf8);
+  let mut state = game.start_state();
+  let mut search = Search::new(
+    game.clone(),
+    Arc::new(state.clone()),
+    1,
+    SearchLimit::new(iterations),
+    Uct(1.2),
+    RandomRollout(40),
+  );
+  if open_loop {
+    search = search.with_open_loop();
+  }
+  let mut score = 0.0;
+  while !game.check_terminal(&state) {
+    let mut worker = search.create_workers(1);
+    search.start(&mut worker[0]);
+    let action = search
+      .get_policy()
+      .into_iter()
+      .max_by(|a, b| a.probability.total_cmp(&b.probability))
+      .unwrap()
+      .action;
+    let outcome = game.apply_action(&mut state, &action);
+    score += outcome[0].0;
+    let observations: Vec<_> = outcome.into_iter().map(|(_, o)| o).collect();
+    search.advance(Arc::new(state.clone()), &action, &observations);
+  }
+  (score, state.largest_tile())
+}
+
+fn main() {
+  let mut args = std::env::args().skip(1);
+  let games: u32 = args.next().map_or(20, |a| a.parse().unwrap());
+  let iterations: u32 = args.next().map_or(2000, |a| a.parse().unwrap());
+  for open_loop in [false, true] {
+    let results: Vec<_> = crossbeam::scope(|s| {
+      let handles: Vec<_> = (0..games)
+        .map(|_| s.spawn(move |_| play(open_loop, iterations)))
+        .collect();
+      handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+    .unwrap();
+    let score = results.iter().map(|(s, _)| s).sum::<f32>() / games as f32;
+    let tile = results.iter().map(|(_, t)| *t as f32).sum::<f32>() / games as f32;
+    let name = if open_loop {
+      "open loop"
+    } else {
+      "closed loop"
+    };
+    println!("{name}: average score {score:.1}, average largest tile {tile:.1}");
+  }
+}
@@ -5,7 +5,7 @@ use std::{
 
 use lib::MctsProblem;
 use mcts::{bandits::Uct, rollout::RandomRollout, SearchLimit};
-use ml::{accumulate_rewards, playout};
+use ml::{accumulate_rewards, playout, PlayoutOptions};
 use tzf8::Tzf8;
 
 fn main() {
@@ -27,7 +27,7 @@ fn main() {
             bandit_policy,
             u32::MAX,
             RandomRollout(40),
-            true,
+            PlayoutOptions::default(),
           );
           let r = accumulate_rewards(&Tzf8, &t);
           let largest_tile = start.largest_tile();
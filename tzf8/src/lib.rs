@@ -1,6 +1,6 @@
 use std::fmt::{Debug, Display};
 
-use lib::MctsProblem;
+use lib::{ChanceOutcome, MctsProblem};
 use rand::{seq::IteratorRandom, Rng};
 
 pub struct Tzf8;
@@ -74,6 +74,37 @@ impl MctsProblem for Tzf8 {
     }
   }
 
+  fn chance_outcomes(
+    &self,
+    h_state: &Self::HiddenState,
+    action: &Self::Action,
+  ) -> Option<Vec<ChanceOutcome<Self::Observation>>> {
+    let mut moved = h_state.clone();
+    if !moved.apply_move(action) {
+      return Some(vec![(1.0, vec![(0.0, Observation::End)])]);
+    }
+    let empty_cells = moved.empty_cells();
+    let p = 1.0 / empty_cells.len() as f32;
+    let mut result = Vec::with_capacity(empty_cells.len() * 2);
+    for (r, c) in empty_cells {
+      for (v, pv) in [(2, 0.9), (4, 0.1)] {
+        result.push((
+          p * pv,
+          vec![(
+            v as f32,
+            Observation::Result {
+              shift: *action,
+              v,
+              x: r as u8,
+              y: c as u8,
+            },
+          )],
+        ));
+      }
+    }
+    Some(result)
+  }
+
   fn belief_update(&self, b_state: &mut Self::BeliefState, obs: &Self::Observation) {
     match obs {
       Observation::End => {
@@ -198,9 +229,9 @@ impl State {
     let empty_cells = self.empty_cells();
     let (r, c) = empty_cells
       .into_iter()
-      .choose(&mut rand::thread_rng())
+      .choose(&mut lib::random::rng())
       .unwrap();
-    let p: f32 = rand::thread_rng().gen();
+    let p: f32 = lib::random::rng().gen();
     let v = if p < 0.9 {
       self.board[r][c] = 2;
       2
@@ -307,7 +338,7 @@ mod tests {
   use mcts::{
     bandits::Uct, forest::render::save, rollout::RandomRollout, search::Search, SearchLimit,
   };
-  use ml::{accumulate_rewards, playout};
+  use ml::{accumulate_rewards, playout, PlayoutOptions};
 
   use crate::*;
 
@@ -344,6 +375,21 @@ mod tests {
     save(&forest, File::create("tzf8.dot").unwrap(), 500, 5);
   }
 
+  #[test]
+  fn test_chance_outcomes() {
+    let mut state = State::new();
+    state.place(2, 0, 0);
+    state.place(4, 1, 0);
+    // moving left changes nothing and ends the game
+    let outcomes = Tzf8.chance_outcomes(&state, &Move::Left).unwrap();
+    assert_eq!(outcomes.len(), 1);
+    // moving right leaves 14 empty cells for a 2 or a 4
+    let outcomes = Tzf8.chance_outcomes(&state, &Move::Right).unwrap();
+    assert_eq!(outcomes.len(), 28);
+    let total: f32 = outcomes.iter().map(|(p, _)| p).sum();
+    assert!((total - 1.0).abs() < 1e-5);
+  }
+
   #[test]
   fn test_tzf8_playout() {
     let m = Arc::new(Tzf8);
@@ -358,7 +404,7 @@ mod tests {
       bandit_policy,
       u32::MAX,
       RandomRollout(20),
-      true,
+      PlayoutOptions::default(),
     );
     let r = accumulate_rewards(&Tzf8, &t);
     println!("{}", start);